@@ -0,0 +1,221 @@
+// Files embedded in the target, such as the XML of an e-invoice. qpdf keeps
+// the primary input's embedded-files name tree and its associated files (the
+// catalog's /AF, for PDF/A-3), so the merged file is checked to still carry
+// them before it replaces the original.
+
+use lopdf::{Dictionary, Document, Object};
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+// Names of the files in the /EmbeddedFiles name tree, sorted
+fn embedded_file_names(doc: &Document) -> Vec<Vec<u8>> {
+    let mut names = Vec::new();
+    let root = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| resolve(doc, catalog.get(b"Names").ok()?))
+        .and_then(|names| names.as_dict().ok())
+        .and_then(|names| resolve(doc, names.get(b"EmbeddedFiles").ok()?))
+        .and_then(|tree| tree.as_dict().ok());
+
+    let mut pending: Vec<&Dictionary> = root.into_iter().collect();
+    // Bounded, in case the tree refers back to itself
+    let mut visited = 0;
+    while let Some(node) = pending.pop() {
+        visited += 1;
+        if visited > 10_000 {
+            break;
+        }
+        if let Some(pairs) = node
+            .get(b"Names")
+            .ok()
+            .and_then(|pairs| resolve(doc, pairs)?.as_array().ok())
+        {
+            names.extend(
+                pairs
+                    .iter()
+                    .step_by(2)
+                    .filter_map(|name| name.as_str().ok().map(<[u8]>::to_vec)),
+            );
+        }
+        if let Some(kids) = node
+            .get(b"Kids")
+            .ok()
+            .and_then(|kids| resolve(doc, kids)?.as_array().ok())
+        {
+            pending.extend(
+                kids.iter()
+                    .filter_map(|kid| resolve(doc, kid)?.as_dict().ok()),
+            );
+        }
+    }
+    names.sort();
+    names
+}
+
+// Number of the catalog's associated files
+fn associated_file_count(doc: &Document) -> usize {
+    doc.catalog()
+        .ok()
+        .and_then(|catalog| resolve(doc, catalog.get(b"AF").ok()?))
+        .and_then(|files| files.as_array().ok())
+        .map_or(0, Vec::len)
+}
+
+// The attachments of a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Attachments {
+    names: Vec<Vec<u8>>,
+    associated: usize,
+}
+
+impl Attachments {
+    pub(crate) fn of(doc: &Document) -> Self {
+        Attachments {
+            names: embedded_file_names(doc),
+            associated: associated_file_count(doc),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.associated == 0
+    }
+}
+
+// Check that the merged `doc` still carries the target's `expected` attachments
+pub(crate) fn check_merged(doc: &Document, expected: &Attachments) -> Result<(), String> {
+    let merged = Attachments::of(doc);
+    let missing: Vec<String> = expected
+        .names
+        .iter()
+        .filter(|name| !merged.names.contains(name))
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "embedded files were lost in the merge: {}",
+            missing.join(", ")
+        ));
+    }
+    if merged.associated < expected.associated {
+        return Err(format!(
+            "{} of {} associated files were lost in the merge",
+            expected.associated - merged.associated,
+            expected.associated
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::position::Position;
+    use lopdf::{dictionary, Stream};
+
+    // Embed a file named `name` in `doc`, also associated with it (/AF)
+    fn embed(doc: &mut Document, name: &str) {
+        let file_id = doc.add_object(Stream::new(
+            dictionary! { "Type" => "EmbeddedFile" },
+            b"<Invoice/>".to_vec(),
+        ));
+        let spec_id = doc.add_object(dictionary! {
+            "Type" => "Filespec",
+            "F" => Object::string_literal(name),
+            "UF" => Object::string_literal(name),
+            "EF" => dictionary! { "F" => file_id },
+            "AFRelationship" => "Data",
+        });
+
+        let catalog = doc.catalog_mut().unwrap();
+        if !catalog.has(b"Names") {
+            catalog.set(
+                "Names",
+                dictionary! { "EmbeddedFiles" => dictionary! { "Names" => Vec::<Object>::new() } },
+            );
+            catalog.set("AF", Vec::<Object>::new());
+        }
+        let tree = catalog
+            .get_mut(b"Names")
+            .and_then(Object::as_dict_mut)
+            .and_then(|names| names.get_mut(b"EmbeddedFiles"))
+            .and_then(Object::as_dict_mut)
+            .unwrap();
+        let pairs = tree
+            .get_mut(b"Names")
+            .and_then(Object::as_array_mut)
+            .unwrap();
+        pairs.push(Object::string_literal(name));
+        pairs.push(spec_id.into());
+        catalog
+            .get_mut(b"AF")
+            .and_then(Object::as_array_mut)
+            .unwrap()
+            .push(spec_id.into());
+    }
+
+    #[test]
+    fn finds_embedded_and_associated_files() {
+        let mut doc = fixtures::pdf(1);
+        assert!(Attachments::of(&doc).is_empty());
+
+        embed(&mut doc, "invoice.xml");
+        embed(&mut doc, "attachment.txt");
+        let attachments = Attachments::of(&doc);
+        assert_eq!(
+            attachments.names,
+            [b"attachment.txt".to_vec(), b"invoice.xml".to_vec()]
+        );
+        assert_eq!(attachments.associated, 2);
+        assert_eq!(check_merged(&doc, &attachments), Ok(()));
+    }
+
+    #[test]
+    fn finds_files_in_the_kids_of_the_name_tree() {
+        let mut doc = fixtures::pdf(1);
+        let spec_id = doc.add_object(dictionary! { "Type" => "Filespec" });
+        let leaf_id = doc.add_object(dictionary! {
+            "Names" => vec![Object::string_literal("invoice.xml"), spec_id.into()],
+        });
+        doc.catalog_mut().unwrap().set(
+            "Names",
+            dictionary! { "EmbeddedFiles" => dictionary! { "Kids" => vec![leaf_id.into()] } },
+        );
+        assert_eq!(Attachments::of(&doc).names, [b"invoice.xml".to_vec()]);
+    }
+
+    #[test]
+    fn check_fails_when_files_are_lost() {
+        let mut doc = fixtures::pdf(1);
+        embed(&mut doc, "invoice.xml");
+        let expected = Attachments::of(&doc);
+
+        doc.catalog_mut().unwrap().remove(b"AF");
+        let error = check_merged(&doc, &expected).unwrap_err();
+        assert!(error.contains("1 of 1 associated files"), "{}", error);
+
+        doc.catalog_mut().unwrap().remove(b"Names");
+        let error = check_merged(&doc, &expected).unwrap_err();
+        assert!(error.contains("invoice.xml"), "{}", error);
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn attachments_survive_the_insertion() {
+        let mut target = fixtures::pdf(2);
+        embed(&mut target, "invoice.xml");
+        let expected = Attachments::of(&target);
+
+        let insertion = fixtures::insertion("target", vec![1], Position::Back);
+        let merged = fixtures::insert("attachments-merge", target, fixtures::pdf(1), insertion);
+
+        assert_eq!(merged.get_pages().len(), 3);
+        assert_eq!(Attachments::of(&merged), expected);
+    }
+}
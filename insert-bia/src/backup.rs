@@ -0,0 +1,288 @@
+use crate::cleanup::Leftover;
+use crate::qpdf::{replace_with_temp, temp_output_path};
+use crate::scan::relative_path;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+// Files renamed after processing, recorded in the backup directory so a
+// rollback can remove them: one JSON record per line, the last one for a
+// backup counting
+const RENAMES_FILE: &str = ".insert-bia-renames.jsonl";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RenameRecord {
+    // The backup, relative to the backup directory
+    backup: PathBuf,
+    // File name the processed file was given, in the original's directory
+    renamed: String,
+}
+
+// Where the original of `pdf_path` is kept: the same path relative to the
+// scanned directory, under `backup_dir`. With several scanned directories
+// each gets a subdirectory named after it.
+pub fn backup_path(pdf_path: &Path, base_dirs: &[PathBuf], backup_dir: &Path) -> PathBuf {
+    backup_dir.join(relative_path(pdf_path, base_dirs))
+}
+
+// Copy the original into the backup directory before it is overwritten. An
+// existing backup is kept as is: after a repeated run the file on disk is no
+// longer the original.
+pub fn backup_original(
+    pdf_path: &Path,
+    base_dirs: &[PathBuf],
+    backup_dir: &Path,
+) -> io::Result<PathBuf> {
+    let target = backup_path(pdf_path, base_dirs, backup_dir);
+
+    if target.exists() {
+        return Ok(target);
+    }
+
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(pdf_path, &target)?;
+
+    Ok(target)
+}
+
+// Record that the file backed up as `backup` was renamed to `renamed` after
+// processing
+pub fn record_rename(backup_dir: &Path, backup: &Path, renamed: &Path) -> io::Result<()> {
+    let record = RenameRecord {
+        backup: backup
+            .strip_prefix(backup_dir)
+            .unwrap_or(backup)
+            .to_path_buf(),
+        renamed: renamed
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(backup_dir.join(RENAMES_FILE))?;
+    writeln!(file, "{}", serde_json::to_string(&record)?)?;
+    file.sync_all()
+}
+
+// The names recorded by `record_rename`, by backup relative to `backup_dir`
+fn read_renames(backup_dir: &Path) -> io::Result<HashMap<PathBuf, String>> {
+    let content = match fs::read_to_string(backup_dir.join(RENAMES_FILE)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => return Err(e),
+    };
+    let mut renames = HashMap::new();
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        match serde_json::from_str::<RenameRecord>(line) {
+            Ok(record) => {
+                renames.insert(record.backup, record.renamed);
+            }
+            // A line cut short by a crash
+            Err(e) => warn!("Skipping unreadable line in {}: {}", RENAMES_FILE, e),
+        }
+    }
+    Ok(renames)
+}
+
+// A backed-up original and where it goes back to
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Backup {
+    pub backup: PathBuf,
+    // The file in the scanned directory it is restored over
+    pub target: PathBuf,
+    // The processed file under its new name, removed when the original is
+    // restored
+    pub renamed: Option<PathBuf>,
+}
+
+// Every backed-up original under `backup_dir`, with the file in `dirs` it
+// would be restored over (the reverse of `backup_path`)
+pub fn find_backups(backup_dir: &Path, dirs: &[PathBuf]) -> io::Result<Vec<Backup>> {
+    let renames = read_renames(backup_dir)?;
+    let mut backups = Vec::new();
+    let mut pending = vec![backup_dir.to_path_buf()];
+
+    while let Some(current) = pending.pop() {
+        for entry in fs::read_dir(&current)? {
+            let path = entry?.path();
+
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.is_file() && path != backup_dir.join(RENAMES_FILE) {
+                let relative = path.strip_prefix(backup_dir).unwrap_or(&path);
+                match restore_target(relative, dirs) {
+                    Some(target) => {
+                        let renamed = renames
+                            .get(relative)
+                            .map(|name| target.with_file_name(name))
+                            .filter(|renamed| *renamed != target);
+                        backups.push(Backup {
+                            backup: path,
+                            target,
+                            renamed,
+                        });
+                    }
+                    None => warn!("No directory to restore {} into", path.display()),
+                }
+            }
+        }
+    }
+
+    backups.sort();
+    Ok(backups)
+}
+
+fn restore_target(relative: &Path, dirs: &[PathBuf]) -> Option<PathBuf> {
+    if let [dir] = dirs {
+        return Some(dir.join(relative));
+    }
+
+    // The first component names the scanned directory
+    let mut components = relative.components();
+    let name = components.next()?.as_os_str();
+    let dir = dirs.iter().find(|dir| dir.file_name() == Some(name))?;
+    Some(dir.join(components.as_path()))
+}
+
+// Copy a backed-up original back over the modified file, through a temp
+// file renamed over it so an interrupted copy never leaves it truncated, then
+// remove the processed file under its new name. The backup itself is left
+// in place.
+pub fn restore_backup(backup: &Backup) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = backup.target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let temp = temp_output_path(&backup.target);
+    let _leftover = Leftover::file(&temp);
+    if let Err(e) = fs::copy(&backup.backup, &temp) {
+        let _ = fs::remove_file(&temp);
+        return Err(e.into());
+    }
+    replace_with_temp(&temp, &backup.target)?;
+
+    if let Some(renamed) = &backup.renamed {
+        match fs::remove_file(renamed) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => {
+                return Err(format!(
+                    "Restored, but failed to remove {}: {}",
+                    renamed.display(),
+                    e
+                )
+                .into())
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    // The names of the entries of `dir`, sorted
+    fn entries(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn restores_the_original_over_the_processed_file() {
+        let root = fixtures::scratch_dir("backup-restore");
+        let dir = root.join("files");
+        let backup_dir = root.join("backup");
+        let pdf_path = dir.join("customer").join("a.pdf");
+        fs::create_dir_all(pdf_path.parent().unwrap()).unwrap();
+        fs::write(&pdf_path, "original").unwrap();
+
+        let dirs = [dir.clone()];
+        let backup = backup_original(&pdf_path, &dirs, &backup_dir).unwrap();
+        assert_eq!(backup, backup_dir.join("customer").join("a.pdf"));
+        fs::write(&pdf_path, "processed").unwrap();
+        // An existing backup is kept
+        backup_original(&pdf_path, &dirs, &backup_dir).unwrap();
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "original");
+
+        let backups = find_backups(&backup_dir, &dirs).unwrap();
+        assert_eq!(
+            backups,
+            [Backup {
+                backup: backup.clone(),
+                target: pdf_path.clone(),
+                renamed: None,
+            }]
+        );
+        restore_backup(&backups[0]).unwrap();
+        assert_eq!(fs::read_to_string(&pdf_path).unwrap(), "original");
+        // No temp file left behind, and the backup kept
+        assert_eq!(entries(pdf_path.parent().unwrap()), ["a.pdf"]);
+        assert!(backup.exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn removes_the_renamed_output_when_restoring() {
+        let root = fixtures::scratch_dir("backup-renamed");
+        let dir = root.join("files");
+        let backup_dir = root.join("backup");
+        let pdf_path = dir.join("a.pdf");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(&pdf_path, "original").unwrap();
+
+        let dirs = [dir.clone()];
+        let backup = backup_original(&pdf_path, &dirs, &backup_dir).unwrap();
+        let renamed = dir.join("HD001 - a.pdf");
+        fs::write(&pdf_path, "processed").unwrap();
+        fs::rename(&pdf_path, &renamed).unwrap();
+        record_rename(&backup_dir, &backup, &renamed).unwrap();
+
+        // The record of renames is not a backup
+        let backups = find_backups(&backup_dir, &dirs).unwrap();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(backups[0].target, pdf_path);
+        assert_eq!(backups[0].renamed, Some(renamed.clone()));
+
+        restore_backup(&backups[0]).unwrap();
+        assert_eq!(entries(&dir), ["a.pdf"]);
+        assert_eq!(fs::read_to_string(&pdf_path).unwrap(), "original");
+        // Restoring again finds nothing left to remove
+        restore_backup(&backups[0]).unwrap();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn restores_into_the_directory_each_backup_came_from() {
+        let root = fixtures::scratch_dir("backup-dirs");
+        let dirs = [root.join("north"), root.join("south")];
+        let backup_dir = root.join("backup");
+        for dir in &dirs {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("a.pdf"), "original").unwrap();
+            backup_original(&dir.join("a.pdf"), &dirs, &backup_dir).unwrap();
+        }
+        fs::create_dir_all(backup_dir.join("elsewhere")).unwrap();
+        fs::write(backup_dir.join("elsewhere").join("b.pdf"), "unknown").unwrap();
+
+        let targets: Vec<PathBuf> = find_backups(&backup_dir, &dirs)
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.target)
+            .collect();
+        assert_eq!(targets, [dirs[0].join("a.pdf"), dirs[1].join("a.pdf")]);
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
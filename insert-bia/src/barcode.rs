@@ -0,0 +1,99 @@
+// Barcodes and QR codes stamped on a PDF's first page, read by rendering the
+// page with pdftoppm (poppler) and decoding the image with zbarimg (zbar)
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Resolution the page is rendered at; small codes need more than the screen's
+const RENDER_DPI: &str = "300";
+
+// Run in their own process group like qpdf, so Ctrl+C only stops this process
+fn command(program: &str) -> Command {
+    let mut command = Command::new(program);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+}
+
+// Whether the tools needed to read barcodes can be run
+pub fn check_tools_installed() -> Result<(), String> {
+    for (program, package) in [("pdftoppm", "poppler-utils"), ("zbarimg", "zbar-tools")] {
+        let found = command(program).arg("-h").output().is_ok();
+        if !found {
+            return Err(format!(
+                "{} is not installed or not in PATH (it is in the {} package)",
+                program, package
+            ));
+        }
+    }
+    Ok(())
+}
+
+// The values of the barcodes and QR codes on the first page of `pdf_path`, in
+// the order zbarimg finds them; empty when there are none
+pub fn read_barcodes(pdf_path: &Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let prefix = render_prefix(pdf_path);
+    let mut image = prefix.clone().into_os_string();
+    image.push(".png");
+    let image = PathBuf::from(image);
+
+    let output = command("pdftoppm")
+        .args([
+            "-png",
+            "-r",
+            RENDER_DPI,
+            "-f",
+            "1",
+            "-l",
+            "1",
+            "-singlefile",
+        ])
+        .arg(pdf_path)
+        .arg(&prefix)
+        .output()?;
+    if !output.status.success() {
+        let _ = fs::remove_file(&image);
+        return Err(format!(
+            "pdftoppm failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    let output = command("zbarimg")
+        .args(["--raw", "-q"])
+        .arg(&image)
+        .output();
+    let _ = fs::remove_file(&image);
+    let output = output?;
+
+    // zbarimg exits with 4 when the image has no codes
+    match output.status.code() {
+        Some(0) => Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()),
+        Some(4) => Ok(Vec::new()),
+        _ => Err(format!(
+            "zbarimg failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into()),
+    }
+}
+
+// Where the page of `pdf_path` is rendered, without the extension pdftoppm adds
+fn render_prefix(pdf_path: &Path) -> PathBuf {
+    let stem = pdf_path
+        .file_stem()
+        .and_then(|n| n.to_str())
+        .unwrap_or("page");
+    env::temp_dir().join(format!("insert-bia-{}-{}", std::process::id(), stem))
+}
@@ -0,0 +1,105 @@
+// Bates numbers: a serial number ("ACME000042") stamped in the bottom right
+// corner of every page of every processed file, continuing from one file to
+// the next across the run, for legal and audit bundles.
+
+use crate::footer::{self, Align};
+use std::path::Path;
+
+pub const DEFAULT_DIGITS: usize = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Bates {
+    pub prefix: String,
+    // Number of the first page of the run
+    pub start: u64,
+    // Numbers are padded with zeros to this many digits
+    pub digits: usize,
+}
+
+impl Bates {
+    pub fn label(&self, number: u64) -> String {
+        format!("{}{:0width$}", self.prefix, number, width = self.digits)
+    }
+
+    // "ACME000001-ACME000012", or a single label for a one-page file
+    pub fn range(&self, first: u64, last: u64) -> String {
+        if first == last {
+            self.label(first)
+        } else {
+            format!("{}-{}", self.label(first), self.label(last))
+        }
+    }
+
+    // The number of the last page in a range written by `range`, if it has
+    // this prefix
+    pub fn last_number(&self, range: &str) -> Option<u64> {
+        let digits = range.len() - range.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+        let (rest, number) = range.split_at(range.len() - digits);
+        if !rest.ends_with(self.prefix.as_str()) {
+            return None;
+        }
+        number.parse().ok()
+    }
+}
+
+// Stamp the numbers from `first` on onto the pages of `pdf_path`, in order,
+// rewriting the file. Returns the number of the last page.
+pub(crate) fn stamp_numbers(
+    pdf_path: &Path,
+    bates: &Bates,
+    first: u64,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut last = first;
+    footer::stamp_footers(pdf_path, Align::Right, |index| {
+        last = first + index as u64;
+        Some(bates.label(last))
+    })?;
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bates() -> Bates {
+        Bates {
+            prefix: "ACME".to_string(),
+            start: 1,
+            digits: DEFAULT_DIGITS,
+        }
+    }
+
+    #[test]
+    fn labels() {
+        let bates = bates();
+        assert_eq!(bates.label(42), "ACME000042");
+        // Wider than the padding, never cut
+        assert_eq!(bates.label(12_345_678), "ACME12345678");
+        assert_eq!(bates.range(1, 12), "ACME000001-ACME000012");
+        assert_eq!(bates.range(7, 7), "ACME000007");
+        let bare = Bates {
+            prefix: String::new(),
+            ..bates
+        };
+        assert_eq!(bare.range(1, 2), "000001-000002");
+    }
+
+    #[test]
+    fn last_numbers() {
+        let bates = bates();
+        assert_eq!(bates.last_number("ACME000001-ACME000012"), Some(12));
+        assert_eq!(bates.last_number("ACME000007"), Some(7));
+        assert_eq!(
+            bates.last_number(&bates.label(12_345_678)),
+            Some(12_345_678)
+        );
+        assert_eq!(bates.last_number("OTHER000012"), None);
+        assert_eq!(bates.last_number("ACME"), None);
+        assert_eq!(bates.last_number(""), None);
+        let bare = Bates {
+            prefix: String::new(),
+            ..bates
+        };
+        assert_eq!(bare.last_number("000001-000002"), Some(2));
+    }
+}
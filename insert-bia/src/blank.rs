@@ -0,0 +1,239 @@
+// Generated blank pages, the size of the target's first page, inserted as a
+// separator for double-sided printing or in place of the cover
+
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+// Where a blank page goes relative to the inserted cover pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlankPage {
+    Before,
+    After,
+    // Only the blank page, no cover pages
+    Instead,
+}
+
+impl FromStr for BlankPage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "before" => Ok(BlankPage::Before),
+            "after" => Ok(BlankPage::After),
+            "instead" => Ok(BlankPage::Instead),
+            _ => Err(format!(
+                "invalid blank page '{}' (expected before, after or instead)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for BlankPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlankPage::Before => write!(f, "before"),
+            BlankPage::After => write!(f, "after"),
+            BlankPage::Instead => write!(f, "instead"),
+        }
+    }
+}
+
+// Used when the target's page size cannot be read
+pub(crate) const A4_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 595.0, 842.0];
+
+// The /MediaBox of the first page of `pdf_path`
+pub(crate) fn first_page_media_box(pdf_path: &Path) -> Option<[f32; 4]> {
+    let doc = Document::load(pdf_path).ok()?;
+    page_media_box(&doc, *doc.get_pages().get(&1)?)
+}
+
+// The /MediaBox of the page `id`, which may be inherited from the page tree
+pub(crate) fn page_media_box(doc: &Document, id: ObjectId) -> Option<[f32; 4]> {
+    page_box(inherited(doc, id, b"MediaBox")?)
+}
+
+// The part of the page `id` that is shown: its /CropBox, cut down to its
+// /MediaBox, or the whole /MediaBox when it has none. Corners come in order,
+// left bottom right top.
+pub(crate) fn page_crop_box(doc: &Document, id: ObjectId) -> Option<[f32; 4]> {
+    let [left, bottom, right, top] = ordered(page_media_box(doc, id)?);
+    let media_box = [left, bottom, right, top];
+    let Some(crop_box) = inherited(doc, id, b"CropBox").and_then(page_box) else {
+        return Some(media_box);
+    };
+    let [crop_left, crop_bottom, crop_right, crop_top] = ordered(crop_box);
+    let shown = [
+        crop_left.max(left),
+        crop_bottom.max(bottom),
+        crop_right.min(right),
+        crop_top.min(top),
+    ];
+    if shown[0] < shown[2] && shown[1] < shown[3] {
+        Some(shown)
+    } else {
+        Some(media_box)
+    }
+}
+
+// How far the page `id` is turned clockwise when shown: 0, 90, 180 or 270
+// degrees, from its /Rotate, which may be inherited like its boxes
+pub(crate) fn page_rotation(doc: &Document, id: ObjectId) -> i64 {
+    let degrees = inherited(doc, id, b"Rotate")
+        .and_then(|rotate| rotate.as_float().ok())
+        .map_or(0, |degrees| (degrees as i64).rem_euclid(360));
+    // Anything else is invalid, and shown unturned
+    if degrees % 90 == 0 {
+        degrees
+    } else {
+        0
+    }
+}
+
+// The entry `key` of the page `id` or, failing that, of the nearest of its
+// parents that has it
+fn inherited<'a>(doc: &'a Document, mut id: ObjectId, key: &[u8]) -> Option<&'a Object> {
+    loop {
+        let node = doc.get_dictionary(id).ok()?;
+        if let Ok(value) = node.get(key) {
+            return match value {
+                Object::Reference(reference) => doc.get_object(*reference).ok(),
+                value => Some(value),
+            };
+        }
+        id = node.get(b"Parent").ok()?.as_reference().ok()?;
+    }
+}
+
+fn page_box(object: &Object) -> Option<[f32; 4]> {
+    let numbers: Vec<f32> = object
+        .as_array()
+        .ok()?
+        .iter()
+        .map(|n| n.as_float().ok())
+        .collect::<Option<_>>()?;
+    numbers.try_into().ok()
+}
+
+// Boxes may give any two opposite corners
+fn ordered([x1, y1, x2, y2]: [f32; 4]) -> [f32; 4] {
+    [x1.min(x2), y1.min(y2), x1.max(x2), y1.max(y2)]
+}
+
+// Make `doc` a document with a single page of `media_box`, drawn by
+// `contents` with `resources`
+pub(crate) fn add_only_page(
+    doc: &mut Document,
+    media_box: [f32; 4],
+    contents: Vec<u8>,
+    resources: Dictionary,
+) {
+    let pages_id = doc.new_object_id();
+    let content_id = doc.add_object(Stream::new(dictionary! {}, contents));
+    let resources_id = doc.add_object(resources);
+    let page_id = doc.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => pages_id,
+        "MediaBox" => media_box.iter().map(|&n| Object::Real(n)).collect::<Vec<_>>(),
+        "Contents" => content_id,
+        "Resources" => resources_id,
+    });
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => vec![page_id.into()],
+            "Count" => 1,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! {
+        "Type" => "Catalog",
+        "Pages" => pages_id,
+    });
+    doc.trailer.set("Root", catalog_id);
+}
+
+// Write a one-page PDF with an empty page the size of `target`'s first page
+// (A4 when it cannot be read) to `output`
+pub fn write_blank_page(target: &Path, output: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let media_box = first_page_media_box(target).unwrap_or(A4_MEDIA_BOX);
+    let mut doc = Document::with_version("1.5");
+    add_only_page(&mut doc, media_box, Vec::new(), Dictionary::new());
+    doc.save(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn boxed(numbers: [i64; 4]) -> Object {
+        numbers
+            .iter()
+            .map(|&n| Object::Integer(n))
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    #[test]
+    fn crop_boxes() {
+        let mut doc = fixtures::pdf(3);
+        let pages = doc.get_pages();
+        let pages_id = doc
+            .catalog()
+            .unwrap()
+            .get(b"Pages")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        doc.get_dictionary_mut(pages_id)
+            .unwrap()
+            .set("CropBox", boxed([10, 10, 585, 832]));
+        // Given by its top right and bottom left corners, and past the media box
+        doc.get_dictionary_mut(pages[&2])
+            .unwrap()
+            .set("CropBox", boxed([700, 800, 100, -50]));
+        // Outside the page altogether
+        doc.get_dictionary_mut(pages[&3])
+            .unwrap()
+            .set("CropBox", boxed([600, 0, 700, 100]));
+
+        assert_eq!(
+            page_crop_box(&doc, pages[&1]),
+            Some([10.0, 10.0, 585.0, 832.0])
+        );
+        assert_eq!(
+            page_crop_box(&doc, pages[&2]),
+            Some([100.0, 0.0, 595.0, 800.0])
+        );
+        assert_eq!(page_crop_box(&doc, pages[&3]), Some(A4_MEDIA_BOX));
+        assert_eq!(page_media_box(&doc, pages[&1]), Some(A4_MEDIA_BOX));
+    }
+
+    #[test]
+    fn rotations() {
+        let mut doc = fixtures::pdf(4);
+        let pages = doc.get_pages();
+        let pages_id = doc
+            .catalog()
+            .unwrap()
+            .get(b"Pages")
+            .unwrap()
+            .as_reference()
+            .unwrap();
+        doc.get_dictionary_mut(pages_id).unwrap().set("Rotate", 90);
+        doc.get_dictionary_mut(pages[&2])
+            .unwrap()
+            .set("Rotate", -90);
+        doc.get_dictionary_mut(pages[&3])
+            .unwrap()
+            .set("Rotate", 540);
+        doc.get_dictionary_mut(pages[&4]).unwrap().set("Rotate", 45);
+
+        let rotations: Vec<i64> = pages.values().map(|&id| page_rotation(&doc, id)).collect();
+        assert_eq!(rotations, [90, 270, 180, 0]);
+    }
+}
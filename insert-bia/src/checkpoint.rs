@@ -0,0 +1,101 @@
+use crate::FileStatus;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+// One line of the checkpoint file (JSON-lines, append-only)
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CheckpointRecord {
+    pub file: String,
+    pub status: FileStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Bates numbers of the processed file's pages
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bates: Option<String>,
+    // Why the output of a PDF/A file no longer conforms
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pdfa_lost: Option<String>,
+}
+
+pub struct Checkpoint {
+    file: fs::File,
+}
+
+impl Checkpoint {
+    pub fn open(path: &Path, resume: bool) -> io::Result<Self> {
+        let mut file = if resume {
+            fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?
+        } else {
+            fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(path)?
+        };
+
+        // A crash can leave a half-written last line; terminate it so the
+        // next record starts on its own line
+        if resume {
+            let content = fs::read(path)?;
+            if !content.is_empty() && !content.ends_with(b"\n") {
+                file.write_all(b"\n")?;
+            }
+        }
+
+        Ok(Checkpoint { file })
+    }
+
+    pub fn record(&mut self, record: &CheckpointRecord) -> io::Result<()> {
+        let line = serde_json::to_string(record)?;
+        writeln!(self.file, "{}", line)?;
+        // Flush to disk after every file so completed work survives a crash
+        self.file.flush()?;
+        self.file.sync_data()
+    }
+}
+
+pub fn checkpoint_key(pdf_path: &Path) -> String {
+    // Use the canonical path so the same file is recognized across runs,
+    // regardless of how the directory was typed
+    fs::canonicalize(pdf_path)
+        .unwrap_or_else(|_| pdf_path.to_path_buf())
+        .display()
+        .to_string()
+}
+
+pub fn load_checkpoint(path: &Path) -> io::Result<Vec<CheckpointRecord>> {
+    let mut records = Vec::new();
+
+    if !path.exists() {
+        return Ok(records);
+    }
+
+    let content = fs::read_to_string(path)?;
+    for (line_no, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        match serde_json::from_str::<CheckpointRecord>(line) {
+            Ok(record) => records.push(record),
+            Err(e) => {
+                warn!(
+                    "Ignoring invalid checkpoint line {} in {}: {}",
+                    line_no + 1,
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(records)
+}
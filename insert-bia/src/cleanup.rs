@@ -0,0 +1,123 @@
+// Files a run keeps on disk only while it works: directory locks, temp
+// outputs and secret files. Their owners remove them when dropped, but a
+// second Ctrl+C exits at once without running destructors, so they are also
+// registered here for `remove_leftovers` to delete before that exit.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+// Registered paths, with whether the temp files named after them go too
+static REGISTERED: Mutex<Vec<(PathBuf, bool)>> = Mutex::new(Vec::new());
+
+fn registered() -> MutexGuard<'static, Vec<(PathBuf, bool)>> {
+    // A panic while it was held must not keep the files from being removed
+    REGISTERED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// A path registered for as long as this is alive. Dropping it only ends the
+// registration; removing the file is still up to its owner.
+#[derive(Debug)]
+pub struct Leftover {
+    path: PathBuf,
+}
+
+impl Leftover {
+    pub fn file(path: &Path) -> Self {
+        Self::register(path, false)
+    }
+
+    // A temp output, along with the files named after it (".blank.tmp",
+    // ".cover.tmp", ...) that are made on the way
+    pub fn temp_output(path: &Path) -> Self {
+        Self::register(path, true)
+    }
+
+    fn register(path: &Path, named_after: bool) -> Self {
+        registered().push((path.to_path_buf(), named_after));
+        Leftover {
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+impl Drop for Leftover {
+    fn drop(&mut self) {
+        let mut registered = registered();
+        if let Some(index) = registered.iter().rposition(|(path, _)| *path == self.path) {
+            registered.swap_remove(index);
+        }
+    }
+}
+
+// Delete every registered file, for a run about to exit without unwinding
+pub fn remove_leftovers() {
+    remove_all(&registered());
+}
+
+fn remove_all(entries: &[(PathBuf, bool)]) {
+    for (path, named_after) in entries {
+        let _ = fs::remove_file(path);
+        if *named_after {
+            remove_named_after(path);
+        }
+    }
+}
+
+// Remove the ".tmp" files in the directory of `path` whose name starts with
+// its stem
+fn remove_named_after(path: &Path) {
+    let (Some(dir), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return;
+    };
+    let prefix = format!("{}.", stem.to_string_lossy());
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with(&prefix) && name.ends_with(".tmp") {
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn removes_registered_files_and_their_temp_files() {
+        let dir = fixtures::scratch_dir("leftovers");
+        let temp = dir.join(".a.pdf.insert-bia-1.tmp");
+        let blank = dir.join(".a.pdf.insert-bia-1.blank.tmp");
+        let lock = dir.join(".insert-bia.lock");
+        let other = dir.join("a.pdf");
+        for path in [&temp, &blank, &lock, &other] {
+            fs::write(path, "").unwrap();
+        }
+
+        let _temp = Leftover::temp_output(&temp);
+        let _lock = Leftover::file(&lock);
+        // No longer registered once dropped
+        drop(Leftover::file(&other));
+
+        // Only this test's, which `remove_leftovers` would take with the
+        // files of the tests running alongside
+        let entries: Vec<(PathBuf, bool)> = registered()
+            .iter()
+            .filter(|(path, _)| path.starts_with(&dir))
+            .cloned()
+            .collect();
+        assert_eq!(entries.len(), 2);
+        remove_all(&entries);
+
+        assert!(!temp.exists());
+        assert!(!blank.exists());
+        assert!(!lock.exists());
+        assert!(other.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+// Mapping read from a database query, for when the cover assignments already
+// live in another system's tables
+
+use crate::mapping::{self, MappingEntry, MappingReport, SheetOptions};
+use calamine::Data;
+use std::collections::HashMap;
+use std::path::Path;
+
+// Run `query` against the database at `url` and read its result as the rows
+// of a sheet whose header row is the column names, so the column options
+// work with names ("--page-col page"). `url` is "postgres://…" or
+// "postgresql://…", or "sqlite:<path>" or a path for an SQLite file.
+pub fn read_database_mappings(
+    url: &str,
+    query: &str,
+    options: &SheetOptions,
+    cover_dir: &Path,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let rows = if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+        query_postgres(url, query)?
+    } else {
+        let path = url.strip_prefix("sqlite:").unwrap_or(url);
+        query_sqlite(Path::new(path.trim_start_matches("//")), query)?
+    };
+
+    mapping::read_rows(&rows, (0, 0), options, cover_dir)
+}
+
+// `url` without the password, for messages
+pub fn display_url(url: &str) -> String {
+    let Some((scheme, rest)) = url.split_once("://") else {
+        return url.to_string();
+    };
+    match rest.split_once('@') {
+        Some((user, host)) => {
+            let user = user.split(':').next().unwrap_or_default();
+            format!("{}://{}@{}", scheme, user, host)
+        }
+        None => url.to_string(),
+    }
+}
+
+fn query_sqlite(path: &Path, query: &str) -> Result<Vec<Vec<Data>>, Box<dyn std::error::Error>> {
+    use rusqlite::types::ValueRef;
+
+    if !path.exists() {
+        return Err(format!("Database {} not found", path.display()).into());
+    }
+    let connection =
+        rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut statement = connection.prepare(query)?;
+
+    let header: Vec<Data> = statement
+        .column_names()
+        .into_iter()
+        .map(|name| Data::String(name.to_string()))
+        .collect();
+    let columns = header.len();
+    let mut rows = vec![header];
+
+    let mut result = statement.query([])?;
+    while let Some(row) = result.next()? {
+        let mut cells = Vec::with_capacity(columns);
+        for index in 0..columns {
+            cells.push(match row.get_ref(index)? {
+                ValueRef::Integer(int) => Data::Int(int),
+                ValueRef::Real(float) => Data::Float(float),
+                ValueRef::Text(text) => Data::String(String::from_utf8_lossy(text).into_owned()),
+                ValueRef::Null | ValueRef::Blob(_) => Data::Empty,
+            });
+        }
+        rows.push(cells);
+    }
+
+    Ok(rows)
+}
+
+fn query_postgres(url: &str, query: &str) -> Result<Vec<Vec<Data>>, Box<dyn std::error::Error>> {
+    use postgres::types::Type;
+
+    // The error alone says only "error connecting to server"
+    let mut client =
+        postgres::Client::connect(url, postgres::NoTls).map_err(
+            |e| match std::error::Error::source(&e) {
+                Some(cause) => format!("{}: {}", e, cause),
+                None => e.to_string(),
+            },
+        )?;
+    let statement = client.prepare(query)?;
+
+    let header: Vec<Data> = statement
+        .columns()
+        .iter()
+        .map(|column| Data::String(column.name().to_string()))
+        .collect();
+    let mut rows = vec![header];
+
+    for row in client.query(&statement, &[])? {
+        let mut cells = Vec::with_capacity(row.len());
+        for (index, column) in row.columns().iter().enumerate() {
+            let cell = match *column.type_() {
+                Type::INT2 => row
+                    .get::<_, Option<i16>>(index)
+                    .map(|v| Data::Int(v.into())),
+                Type::INT4 => row
+                    .get::<_, Option<i32>>(index)
+                    .map(|v| Data::Int(v.into())),
+                Type::INT8 => row.get::<_, Option<i64>>(index).map(Data::Int),
+                Type::FLOAT4 => row
+                    .get::<_, Option<f32>>(index)
+                    .map(|v| Data::Float(v.into())),
+                Type::FLOAT8 => row.get::<_, Option<f64>>(index).map(Data::Float),
+                Type::BOOL => row.get::<_, Option<bool>>(index).map(Data::Bool),
+                Type::TEXT | Type::VARCHAR | Type::BPCHAR | Type::NAME => {
+                    row.get::<_, Option<String>>(index).map(Data::String)
+                }
+                ref other => {
+                    return Err(format!(
+                        "Column '{}' has type {}; cast it to text or integer in the query",
+                        column.name(),
+                        other
+                    )
+                    .into())
+                }
+            };
+            cells.push(cell.unwrap_or(Data::Empty));
+        }
+        rows.push(cells);
+    }
+
+    Ok(rows)
+}
@@ -0,0 +1,116 @@
+// Mapping files given as an http(s) URL, downloaded into a local cache. The
+// server's ETag is kept next to the copy so later runs download the file
+// again only when it has changed.
+
+use log::{info, warn};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Whether `path` as given on the command line is a URL rather than a file
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|s| s.starts_with("http://") || s.starts_with("https://"))
+}
+
+// Per-user cache directory for downloaded mapping files
+pub fn cache_dir() -> PathBuf {
+    let base = env::var_os("XDG_CACHE_HOME")
+        .or_else(|| env::var_os("LOCALAPPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .unwrap_or_else(env::temp_dir);
+    base.join("insert-bia")
+}
+
+// Local copy of `url` in `cache_dir`, downloaded if the server has a newer
+// one. When the server cannot be reached an existing copy is used, so a
+// scheduled run still works during an outage.
+pub fn fetch_cached(url: &str, cache_dir: &Path) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    fs::create_dir_all(cache_dir)
+        .map_err(|e| format!("Failed to create cache {}: {}", cache_dir.display(), e))?;
+    let path = cache_dir.join(cache_file_name(url));
+    let etag_path = path.with_file_name(format!(
+        "{}.etag",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let etag = if path.exists() {
+        fs::read_to_string(&etag_path).ok()
+    } else {
+        None
+    };
+
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let mut request = agent.get(url);
+    if let Some(etag) = etag.as_deref() {
+        request = request.header("If-None-Match", etag.trim());
+    }
+
+    let mut response = match request.call() {
+        Ok(response) => response,
+        Err(e) if path.exists() => {
+            warn!("Could not reach {} ({}); using the cached copy", url, e);
+            return Ok(path);
+        }
+        Err(e) => return Err(format!("Failed to download {}: {}", url, e).into()),
+    };
+
+    let status = response.status().as_u16();
+    if status == 304 {
+        info!("{} is unchanged; using the cached copy", url);
+        return Ok(path);
+    }
+    if !response.status().is_success() {
+        return Err(format!("Failed to download {}: HTTP {}", url, status).into());
+    }
+
+    let new_etag = response
+        .headers()
+        .get("ETag")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let content = response
+        .body_mut()
+        .with_config()
+        .limit(100 * 1024 * 1024)
+        .read_to_vec()?;
+
+    // Written under a temporary name first so a failed download never
+    // replaces a good copy
+    let partial = path.with_extension("part");
+    fs::write(&partial, &content)?;
+    fs::rename(&partial, &path)?;
+    match new_etag {
+        Some(etag) => fs::write(&etag_path, etag)?,
+        // A stale ETag would make the next run keep this copy forever
+        None => {
+            let _ = fs::remove_file(&etag_path);
+        }
+    }
+    info!("Downloaded {} ({} bytes)", url, content.len());
+
+    Ok(path)
+}
+
+// A file name for the cached copy of `url`: its host and path with anything
+// unusual replaced, keeping the extension the mapping format is taken from
+fn cache_file_name(url: &str) -> String {
+    let without_scheme = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let without_query = without_scheme.split(['?', '#']).next().unwrap_or_default();
+
+    let mut name: String = without_query
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+            _ => '_',
+        })
+        .collect();
+    // Keep the end, where the file name and extension are
+    if name.len() > 150 {
+        name = name[name.len() - 150..].to_string();
+    }
+    name
+}
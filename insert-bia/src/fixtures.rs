@@ -0,0 +1,150 @@
+// Scratch directories, small PDFs and jobs for the tests. Jobs are put
+// together here rather than by `prepare`, which needs qpdf; tests that merge
+// are marked `#[ignore = "needs qpdf"]`, to be run with `--ignored` where it is
+// installed.
+
+use crate::{metadata, qpdf, Insertion, Job, JobConfig};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, Stream};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+// An empty directory of its own for the test `name`
+pub(crate) fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join("insert-bia-tests").join(format!(
+        "{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// A document of `page_count` A4 pages, each showing its number
+pub(crate) fn pdf(page_count: usize) -> Document {
+    let mut doc = Document::with_version("1.7");
+    let pages_id = doc.new_object_id();
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+    });
+    let mut kids = Vec::new();
+    for number in 1..=page_count {
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 24.into()]),
+                Operation::new("Td", vec![100.into(), 700.into()]),
+                Operation::new("Tj", vec![Object::string_literal(number.to_string())]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            "Contents" => content_id,
+            "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+        });
+        kids.push(page_id.into());
+    }
+    doc.objects.insert(
+        pages_id,
+        Object::Dictionary(dictionary! {
+            "Type" => "Pages",
+            "Kids" => kids,
+            "Count" => page_count as i64,
+        }),
+    );
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    doc
+}
+
+pub(crate) fn insertion(
+    key: &str,
+    pages: Vec<u32>,
+    position: crate::position::Position,
+) -> Insertion {
+    Insertion {
+        key: key.to_string(),
+        pages,
+        position,
+        cover: None,
+        output_name: None,
+        excluded: false,
+        remove_pages: Vec::new(),
+        info: metadata::DocumentInfo::default(),
+        encrypt_password: None,
+        password: None,
+        fields: HashMap::new(),
+        similarity: None,
+    }
+}
+
+// A job inserting into each file of the manifest what it lists, as `prepare`
+// would make it for `config`
+pub(crate) fn job(config: JobConfig, manifest: HashMap<PathBuf, Insertion>) -> Job {
+    let bia_page_count = if config.cover_template.is_some() {
+        1
+    } else {
+        Document::load(&config.bia_path)
+            .map(|bia| bia.get_pages().len())
+            .unwrap_or(0)
+    };
+    let template = config
+        .cover_template
+        .as_deref()
+        .map(|path| crate::template::CoverTemplate::load(path).unwrap());
+    let mut pdf_files: Vec<PathBuf> = manifest.keys().cloned().collect();
+    pdf_files.sort();
+    Job {
+        config,
+        bia_page_count,
+        mappings: HashMap::new(),
+        mapping_report: None,
+        pdf_files,
+        manifest: Some(manifest),
+        collisions: Vec::new(),
+        already_done: 0,
+        resumed_keys: HashSet::new(),
+        next_bates: 1,
+        cover_page_counts: HashMap::new(),
+        document_names: HashMap::new(),
+        script_matches: HashMap::new(),
+        template,
+        folder_covers: HashMap::new(),
+        choices: HashMap::new(),
+    }
+}
+
+// Insert `cover` into `target` as `insertion` says, through `execute`, and
+// return the processed file
+pub(crate) fn insert(
+    name: &str,
+    mut target: Document,
+    mut cover: Document,
+    insertion: Insertion,
+) -> Document {
+    assert!(qpdf::check_qpdf_installed(), "qpdf is not installed");
+    let dir = scratch_dir(name);
+    let files = dir.join("files");
+    fs::create_dir_all(&files).unwrap();
+    let target_path = files.join("target.pdf");
+    let bia_path = dir.join("bia.pdf");
+    target.save(&target_path).unwrap();
+    cover.save(&bia_path).unwrap();
+
+    let config = JobConfig::new(vec![files], dir.join("mapping.xlsx"), &bia_path);
+    let job = job(config, HashMap::from([(target_path.clone(), insertion)]));
+    let summary = crate::execute(&job, |_| {}).unwrap();
+    let result = &summary.files[0];
+    assert_eq!(result.error, None, "processing {}", name);
+    let processed = Document::load(&target_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    processed
+}
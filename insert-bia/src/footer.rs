@@ -0,0 +1,268 @@
+// Small text stamped at the foot of the pages of a merged file: Bates numbers
+// and page numbers
+
+use crate::blank::{page_crop_box, page_rotation, A4_MEDIA_BOX};
+use crate::template::win_ansi;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, ObjectId, Stream, StringFormat};
+use std::path::Path;
+
+const FONT_NAME: &[u8] = b"InsertBiaFooter";
+const FONT_SIZE: f32 = 9.0;
+// Average width of a Helvetica character, in units of the font size
+const CHAR_WIDTH: f32 = 0.6;
+// Distance of the text from the bottom edge, and from the right one
+const MARGIN: f32 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Align {
+    Center,
+    Right,
+}
+
+// Stamp the text `footer` gives for each page (by 0-based index, None to leave
+// the page alone) onto the pages of `pdf_path`, rewriting the file
+pub(crate) fn stamp_footers(
+    pdf_path: &Path,
+    align: Align,
+    mut footer: impl FnMut(usize) -> Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(pdf_path)?;
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    for (index, page_id) in page_ids.into_iter().enumerate() {
+        let Some(text) = footer(index) else {
+            continue;
+        };
+        let view = View::of(&doc, page_id);
+        let width = text.chars().count() as f32 * CHAR_WIDTH * FONT_SIZE;
+        let x = match align {
+            Align::Center => (view.width() - width) / 2.0,
+            Align::Right => view.width() - MARGIN - width,
+        };
+        let operations = vec![
+            Operation::new("Q", vec![]),
+            Operation::new("q", vec![]),
+            Operation::new("g", vec![0.into()]),
+            Operation::new("BT", vec![]),
+            Operation::new(
+                "Tf",
+                vec![Object::Name(FONT_NAME.to_vec()), FONT_SIZE.into()],
+            ),
+            Operation::new("Tm", view.text_matrix(x, MARGIN)),
+            Operation::new(
+                "Tj",
+                vec![Object::String(win_ansi(&text), StringFormat::Literal)],
+            ),
+            Operation::new("ET", vec![]),
+            Operation::new("Q", vec![]),
+        ];
+        let stamp = Content { operations }.encode()?;
+        add_font(&mut doc, page_id, font_id)?;
+        wrap_contents(&mut doc, page_id, stamp)?;
+    }
+
+    doc.save(pdf_path)?;
+    Ok(())
+}
+
+// A page as it is shown: the part of it in its crop box, turned by its
+// /Rotate
+struct View {
+    crop_box: [f32; 4],
+    rotation: i64,
+}
+
+impl View {
+    fn of(doc: &Document, page_id: ObjectId) -> Self {
+        View {
+            crop_box: page_crop_box(doc, page_id).unwrap_or(A4_MEDIA_BOX),
+            rotation: page_rotation(doc, page_id),
+        }
+    }
+
+    fn width(&self) -> f32 {
+        let [left, bottom, right, top] = self.crop_box;
+        match self.rotation {
+            90 | 270 => top - bottom,
+            _ => right - left,
+        }
+    }
+
+    // The text matrix that puts upright text at `x`, `y` from the bottom left
+    // corner of the page as shown. Turned the other way from the page, the
+    // text starts from the corner that is shown at the bottom left.
+    fn text_matrix(&self, x: f32, y: f32) -> Vec<Object> {
+        let [left, bottom, right, top] = self.crop_box;
+        let (cos, sin, origin_x, origin_y) = match self.rotation {
+            90 => (0.0, 1.0, right, bottom),
+            180 => (-1.0, 0.0, right, top),
+            270 => (0.0, -1.0, left, top),
+            _ => (1.0, 0.0, left, bottom),
+        };
+        [
+            cos,
+            sin,
+            -sin,
+            cos,
+            origin_x + x * cos - y * sin,
+            origin_y + x * sin + y * cos,
+        ]
+        .into_iter()
+        .map(Object::Real)
+        .collect()
+    }
+}
+
+// Make the font `font_id` available to the page as `FONT_NAME`. Pages written
+// by qpdf carry their own resources rather than inheriting them.
+fn add_font(
+    doc: &mut Document,
+    page_id: ObjectId,
+    font_id: ObjectId,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let resources = doc.get_or_create_resources(page_id)?.as_dict_mut()?;
+    match resources.get_mut(b"Font") {
+        Ok(Object::Reference(id)) => {
+            let id = *id;
+            doc.get_dictionary_mut(id)?.set(FONT_NAME, font_id);
+        }
+        Ok(fonts) => fonts.as_dict_mut()?.set(FONT_NAME, font_id),
+        Err(_) => resources.set("Font", dictionary! { FONT_NAME => font_id }),
+    }
+    Ok(())
+}
+
+// Draw `stamp` over the page, after its own contents inside q/Q so whatever
+// state they leave behind does not move the stamp. `stamp` starts with the Q
+// closing them.
+fn wrap_contents(
+    doc: &mut Document,
+    page_id: ObjectId,
+    stamp: Vec<u8>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let existing = match doc.get_dictionary(page_id)?.get(b"Contents") {
+        Ok(Object::Array(contents)) => contents.clone(),
+        Ok(Object::Reference(id)) => match doc.get_object(*id) {
+            Ok(Object::Array(contents)) => contents.clone(),
+            _ => vec![Object::Reference(*id)],
+        },
+        Ok(contents) => vec![contents.clone()],
+        Err(_) => Vec::new(),
+    };
+    let open_id = doc.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+    let stamp_id = doc.add_object(Stream::new(dictionary! {}, stamp));
+
+    let mut contents = vec![Object::Reference(open_id)];
+    contents.extend(existing);
+    contents.push(Object::Reference(stamp_id));
+    doc.get_dictionary_mut(page_id)?.set("Contents", contents);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::fs;
+
+    // The text matrix of the stamp on each page
+    fn stamp(name: &str, doc: &mut Document, align: Align) -> Vec<Vec<f32>> {
+        let dir = fixtures::scratch_dir(name);
+        let path = dir.join("a.pdf");
+        doc.save(&path).unwrap();
+        stamp_footers(&path, align, |_| Some("AB".to_string())).unwrap();
+
+        let doc = Document::load(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        doc.get_pages()
+            .into_values()
+            .map(|id| {
+                let content = Content::decode(&doc.get_page_content(id)).unwrap();
+                let matrix = content
+                    .operations
+                    .iter()
+                    .find(|op| op.operator == "Tm")
+                    .unwrap();
+                matrix
+                    .operands
+                    .iter()
+                    .map(|n| n.as_float().unwrap())
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Numbers are written with a few decimals only
+    fn assert_near(matrix: &[f32], expected: [f32; 6]) {
+        let near = matrix
+            .iter()
+            .zip(expected)
+            .all(|(n, e)| (n - e).abs() < 0.01);
+        assert!(near, "{:?} is not {:?}", matrix, expected);
+    }
+
+    // Two characters of 9 points
+    const TEXT_WIDTH: f32 = 2.0 * CHAR_WIDTH * FONT_SIZE;
+
+    #[test]
+    fn stamps_inside_the_crop_box() {
+        let mut doc = fixtures::pdf(2);
+        let pages = doc.get_pages();
+        let crop_box: Vec<Object> = [100, 100, 400, 600].into_iter().map(Object::from).collect();
+        doc.get_dictionary_mut(pages[&2])
+            .unwrap()
+            .set("CropBox", crop_box);
+
+        let matrices = stamp("footer-crop", &mut doc, Align::Right);
+        assert_near(
+            &matrices[0],
+            [1.0, 0.0, 0.0, 1.0, 595.0 - MARGIN - TEXT_WIDTH, MARGIN],
+        );
+        assert_near(
+            &matrices[1],
+            [
+                1.0,
+                0.0,
+                0.0,
+                1.0,
+                400.0 - MARGIN - TEXT_WIDTH,
+                100.0 + MARGIN,
+            ],
+        );
+    }
+
+    #[test]
+    fn stamps_upright_on_turned_pages() {
+        let mut doc = fixtures::pdf(3);
+        let pages = doc.get_pages();
+        for (number, rotation) in [(1, 90), (2, 180), (3, 270)] {
+            doc.get_dictionary_mut(pages[&number])
+                .unwrap()
+                .set("Rotate", rotation);
+        }
+
+        let matrices = stamp("footer-rotate", &mut doc, Align::Center);
+        // Centred along the long side once turned a quarter
+        let across_long = (842.0 - TEXT_WIDTH) / 2.0;
+        let across_short = (595.0 - TEXT_WIDTH) / 2.0;
+        assert_near(
+            &matrices[0],
+            [0.0, 1.0, -1.0, 0.0, 595.0 - MARGIN, across_long],
+        );
+        assert_near(
+            &matrices[1],
+            [-1.0, 0.0, 0.0, -1.0, 595.0 - across_short, 842.0 - MARGIN],
+        );
+        assert_near(
+            &matrices[2],
+            [0.0, -1.0, 1.0, 0.0, MARGIN, 842.0 - across_long],
+        );
+    }
+}
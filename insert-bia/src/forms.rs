@@ -0,0 +1,272 @@
+// Annotations (sticky notes, links) and form fields kept through the merge.
+// qpdf copies the annotations of every page it takes and, from 10.2 on,
+// merges the form fields of the cover into the target's form (renaming
+// fields whose names clash). A form field whose widget is on a page but no
+// longer listed in the form cannot be filled in, so the merged file is
+// checked for both before it replaces the original.
+
+use lopdf::{Document, Object, ObjectId};
+use std::collections::HashSet;
+
+// The annotations on a page, as references where they are indirect
+fn page_annotations(doc: &Document, page_id: ObjectId) -> Vec<&Object> {
+    let Ok(page) = doc.get_dictionary(page_id) else {
+        return Vec::new();
+    };
+    let annots = match page.get(b"Annots") {
+        Ok(Object::Reference(id)) => doc.get_object(*id).ok(),
+        Ok(annots) => Some(annots),
+        Err(_) => None,
+    };
+    annots
+        .and_then(|annots| annots.as_array().ok())
+        .map(|annots| annots.iter().collect())
+        .unwrap_or_default()
+}
+
+// Every field and widget reachable from the form's /Fields
+fn form_objects(doc: &Document) -> HashSet<ObjectId> {
+    let mut found = HashSet::new();
+    let fields = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"AcroForm").ok())
+        .and_then(|form| match form {
+            Object::Reference(id) => doc.get_dictionary(*id).ok(),
+            form => form.as_dict().ok(),
+        })
+        .and_then(|form| form.get(b"Fields").ok())
+        .and_then(|fields| match fields {
+            Object::Reference(id) => doc.get_object(*id).ok(),
+            fields => Some(fields),
+        })
+        .and_then(|fields| fields.as_array().ok());
+
+    let mut pending: Vec<ObjectId> = fields
+        .into_iter()
+        .flatten()
+        .filter_map(|field| field.as_reference().ok())
+        .collect();
+    while let Some(id) = pending.pop() {
+        if !found.insert(id) {
+            continue;
+        }
+        if let Ok(kids) = doc
+            .get_dictionary(id)
+            .and_then(|field| field.get(b"Kids"))
+            .and_then(Object::as_array)
+        {
+            pending.extend(kids.iter().filter_map(|kid| kid.as_reference().ok()));
+        }
+    }
+    found
+}
+
+// Annotations on some pages, and how many of them are form widgets missing
+// from the form
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Annotations {
+    pub total: usize,
+    pub unlinked: usize,
+}
+
+impl std::ops::Add for Annotations {
+    type Output = Annotations;
+
+    fn add(self, other: Annotations) -> Annotations {
+        Annotations {
+            total: self.total + other.total,
+            unlinked: self.unlinked + other.unlinked,
+        }
+    }
+}
+
+// The annotations on the given 1-based pages of `doc`, counted once per time
+// a page is listed
+pub(crate) fn count_annotations(doc: &Document, pages: &[u32]) -> Annotations {
+    let form = form_objects(doc);
+    let page_ids = doc.get_pages();
+    let mut count = Annotations::default();
+    for page_id in pages.iter().filter_map(|page| page_ids.get(page)) {
+        for annotation in page_annotations(doc, *page_id) {
+            count.total += 1;
+            let Ok(id) = annotation.as_reference() else {
+                continue;
+            };
+            let is_widget = doc
+                .get_dictionary(id)
+                .and_then(|a| a.get(b"Subtype"))
+                .and_then(Object::as_name)
+                .is_ok_and(|subtype| subtype == b"Widget");
+            if is_widget && !form.contains(&id) {
+                count.unlinked += 1;
+            }
+        }
+    }
+    count
+}
+
+// Check that the merged `doc` kept the `expected` annotations of the pages it
+// was made of, with their form fields still part of its form
+pub(crate) fn check_merged(doc: &Document, expected: Annotations) -> Result<(), String> {
+    let pages: Vec<u32> = doc.get_pages().into_keys().collect();
+    let merged = count_annotations(doc, &pages);
+    if merged.total < expected.total {
+        return Err(format!(
+            "{} of {} annotations were lost in the merge",
+            expected.total - merged.total,
+            expected.total
+        ));
+    }
+    if merged.unlinked > expected.unlinked {
+        return Err(format!(
+            "{} form fields are no longer part of the form (qpdf 10.2 or later merges forms)",
+            merged.unlinked - expected.unlinked
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::position::Position;
+    use lopdf::dictionary;
+
+    // Put a text field named `name` on the page at 0-based `page_index`,
+    // listed in the form when `linked`
+    fn add_field(doc: &mut Document, page_index: usize, name: &str, linked: bool) -> ObjectId {
+        let page_id = doc.get_pages().into_values().nth(page_index).unwrap();
+        let field_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Tx",
+            "T" => Object::string_literal(name),
+            "V" => Object::string_literal("filled in"),
+            "Rect" => vec![100.into(), 600.into(), 300.into(), 620.into()],
+            "P" => page_id,
+        });
+        add_annotation(doc, page_id, field_id);
+        if linked {
+            let catalog = doc.catalog_mut().unwrap();
+            if !catalog.has(b"AcroForm") {
+                catalog.set("AcroForm", dictionary! { "Fields" => Vec::<Object>::new() });
+            }
+            let form = catalog.get_mut(b"AcroForm").unwrap().as_dict_mut().unwrap();
+            form.get_mut(b"Fields")
+                .unwrap()
+                .as_array_mut()
+                .unwrap()
+                .push(field_id.into());
+        }
+        field_id
+    }
+
+    fn add_annotation(doc: &mut Document, page_id: ObjectId, annotation_id: ObjectId) {
+        let page = doc.get_dictionary_mut(page_id).unwrap();
+        if !page.has(b"Annots") {
+            page.set("Annots", Vec::<Object>::new());
+        }
+        page.get_mut(b"Annots")
+            .unwrap()
+            .as_array_mut()
+            .unwrap()
+            .push(annotation_id.into());
+    }
+
+    fn add_note(doc: &mut Document, page_index: usize) {
+        let page_id = doc.get_pages().into_values().nth(page_index).unwrap();
+        let note_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Text",
+            "Contents" => Object::string_literal("a note"),
+            "Rect" => vec![50.into(), 50.into(), 70.into(), 70.into()],
+        });
+        add_annotation(doc, page_id, note_id);
+    }
+
+    #[test]
+    fn counts_annotations_and_unlinked_widgets() {
+        let mut doc = fixtures::pdf(2);
+        add_field(&mut doc, 0, "name", true);
+        add_field(&mut doc, 0, "orphan", false);
+        add_note(&mut doc, 1);
+
+        let all = count_annotations(&doc, &[1, 2]);
+        assert_eq!((all.total, all.unlinked), (3, 1));
+        let second = count_annotations(&doc, &[2]);
+        assert_eq!((second.total, second.unlinked), (1, 0));
+        // A page listed twice counts twice
+        let twice = count_annotations(&doc, &[2, 2]);
+        assert_eq!(twice.total, 2);
+    }
+
+    #[test]
+    fn check_passes_when_the_form_is_kept() {
+        let mut doc = fixtures::pdf(2);
+        add_field(&mut doc, 0, "name", true);
+        add_note(&mut doc, 1);
+        let expected = count_annotations(&doc, &[1, 2]);
+        assert_eq!(check_merged(&doc, expected), Ok(()));
+    }
+
+    #[test]
+    fn check_fails_when_a_field_leaves_the_form() {
+        let mut doc = fixtures::pdf(1);
+        add_field(&mut doc, 0, "name", true);
+        let expected = count_annotations(&doc, &[1]);
+
+        // The widget is still on the page, but the form no longer lists it
+        doc.catalog_mut().unwrap().remove(b"AcroForm");
+        let error = check_merged(&doc, expected).unwrap_err();
+        assert!(error.contains("no longer part of the form"), "{}", error);
+    }
+
+    #[test]
+    fn check_fails_when_annotations_are_lost() {
+        let mut doc = fixtures::pdf(1);
+        add_field(&mut doc, 0, "name", true);
+        add_note(&mut doc, 0);
+        let expected = count_annotations(&doc, &[1]);
+
+        let page_id = doc.get_pages()[&1];
+        doc.get_dictionary_mut(page_id).unwrap().remove(b"Annots");
+        let error = check_merged(&doc, expected).unwrap_err();
+        assert!(error.contains("2 of 2 annotations were lost"), "{}", error);
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn form_fields_survive_the_merge() {
+        let mut target = fixtures::pdf(2);
+        add_field(&mut target, 1, "name", true);
+        add_note(&mut target, 0);
+        let expected = count_annotations(&target, &[1, 2]);
+
+        let insertion = fixtures::insertion("target", vec![1], Position::Front);
+        let merged = fixtures::insert("forms-merge", target, fixtures::pdf(1), insertion);
+
+        assert_eq!(merged.get_pages().len(), 3);
+        assert_eq!(check_merged(&merged, expected), Ok(()));
+        // The field is still in the form, with its widget on the target's
+        // second page, now the third
+        let fields: Vec<_> = form_objects(&merged)
+            .into_iter()
+            .filter(|&id| {
+                merged
+                    .get_dictionary(id)
+                    .and_then(|field| field.get(b"T"))
+                    .and_then(Object::as_str)
+                    .is_ok_and(|name| name == b"name")
+            })
+            .collect();
+        assert_eq!(fields.len(), 1);
+        let page_id = merged.get_pages()[&3];
+        let on_page: Vec<_> = page_annotations(&merged, page_id)
+            .into_iter()
+            .filter_map(|a| a.as_reference().ok())
+            .collect();
+        assert_eq!(on_page, fields);
+    }
+}
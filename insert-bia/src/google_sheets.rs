@@ -0,0 +1,229 @@
+// Mapping read from a Google Sheets spreadsheet over the Sheets API, with a
+// service account that the spreadsheet is shared with
+
+use crate::mapping::{self, MappingEntry, MappingReport, SheetOptions};
+use calamine::Data;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SHEETS_API: &str = "https://sheets.googleapis.com/v4/spreadsheets";
+const SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets.readonly";
+
+// The fields of a service account key file (the JSON downloaded from the
+// Google Cloud console) needed to get an access token
+#[derive(Debug, Deserialize)]
+struct ServiceAccountKey {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct SpreadsheetResponse {
+    sheets: Vec<SheetResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SheetResponse {
+    properties: SheetProperties,
+}
+
+#[derive(Debug, Deserialize)]
+struct SheetProperties {
+    title: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ValuesResponse {
+    #[serde(default)]
+    values: Vec<Vec<serde_json::Value>>,
+}
+
+// The spreadsheet ID in a Google Sheets URL
+// ("https://docs.google.com/spreadsheets/d/<ID>/edit#gid=0"), or `sheet`
+// itself when it is already an ID
+pub fn spreadsheet_id(sheet: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let sheet = sheet.trim();
+    if !sheet.contains('/') {
+        return Ok(sheet.to_string());
+    }
+
+    sheet
+        .split_once("/d/")
+        .and_then(|(_, rest)| rest.split(['/', '?', '#']).next())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| format!("'{}' is not a Google Sheets URL", sheet).into())
+}
+
+// Read the mapping from the spreadsheet `sheet` (URL or ID), signing in with
+// the service account key at `credentials`. Sheets are selected and read
+// like the worksheets of a workbook.
+pub fn read_google_sheet(
+    sheet: &str,
+    credentials: &Path,
+    options: &SheetOptions,
+    cover_dir: &Path,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let id = spreadsheet_id(sheet)?;
+    let agent: ureq::Agent = ureq::Agent::config_builder()
+        .http_status_as_error(false)
+        .build()
+        .into();
+    let token = access_token(&agent, credentials)?;
+
+    let spreadsheet: SpreadsheetResponse = get_json(
+        &agent,
+        &format!("{}/{}?fields=sheets.properties.title", SHEETS_API, id),
+        &token,
+    )?;
+    let sheet_names: Vec<String> = spreadsheet
+        .sheets
+        .into_iter()
+        .map(|sheet| sheet.properties.title)
+        .collect();
+
+    mapping::read_sheets(&sheet_names, options, cover_dir, |name| {
+        // A bare sheet name as the range is the whole sheet, from A1
+        let range = format!("'{}'", name.replace('\'', "''"));
+        let values: ValuesResponse = get_json(
+            &agent,
+            &format!(
+                "{}/{}/values/{}?valueRenderOption=UNFORMATTED_VALUE",
+                SHEETS_API,
+                id,
+                encode_path_segment(&range)
+            ),
+            &token,
+        )?;
+        let rows = values
+            .values
+            .into_iter()
+            .map(|row| row.into_iter().map(cell_data).collect())
+            .collect();
+        Ok((rows, (0, 0)))
+    })
+}
+
+// OAuth access token for the service account, from a signed JWT
+fn access_token(
+    agent: &ureq::Agent,
+    credentials: &Path,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(credentials).map_err(|e| {
+        format!(
+            "Failed to read credentials {}: {}",
+            credentials.display(),
+            e
+        )
+    })?;
+    let key: ServiceAccountKey = serde_json::from_str(&content).map_err(|e| {
+        format!(
+            "{} is not a service account key: {}",
+            credentials.display(),
+            e
+        )
+    })?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let claims = Claims {
+        iss: &key.client_email,
+        scope: SCOPE,
+        aud: &key.token_uri,
+        iat: now,
+        exp: now + 3600,
+    };
+    let signing_key = jsonwebtoken::EncodingKey::from_rsa_pem(key.private_key.as_bytes())?;
+    let assertion = jsonwebtoken::encode(
+        &jsonwebtoken::Header::new(jsonwebtoken::Algorithm::RS256),
+        &claims,
+        &signing_key,
+    )?;
+
+    let mut response = agent.post(&key.token_uri).send_form([
+        ("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"),
+        ("assertion", assertion.as_str()),
+    ])?;
+    if !response.status().is_success() {
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        return Err(format!(
+            "Google sign-in failed for {} (HTTP {}): {}",
+            key.client_email,
+            response.status().as_u16(),
+            body.trim()
+        )
+        .into());
+    }
+    let token: TokenResponse = response.body_mut().read_json()?;
+    Ok(token.access_token)
+}
+
+fn get_json<T: serde::de::DeserializeOwned>(
+    agent: &ureq::Agent,
+    url: &str,
+    token: &str,
+) -> Result<T, Box<dyn std::error::Error>> {
+    let mut response = agent
+        .get(url)
+        .header("Authorization", &format!("Bearer {}", token))
+        .call()?;
+    if !response.status().is_success() {
+        let status = response.status().as_u16();
+        let body = response.body_mut().read_to_string().unwrap_or_default();
+        // The API explains itself in {"error": {"message": ...}}
+        let message = serde_json::from_str::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|error| error["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or(body);
+        return Err(format!(
+            "Google Sheets API error (HTTP {}): {}",
+            status,
+            message.trim()
+        )
+        .into());
+    }
+    Ok(response.body_mut().read_json()?)
+}
+
+// An unformatted cell value as calamine would read it from a workbook
+fn cell_data(value: serde_json::Value) -> Data {
+    match value {
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(int) => Data::Int(int),
+            None => Data::Float(number.as_f64().unwrap_or_default()),
+        },
+        serde_json::Value::String(text) => Data::String(text),
+        serde_json::Value::Bool(flag) => Data::Bool(flag),
+        _ => Data::Empty,
+    }
+}
+
+// Percent-encode everything but unreserved characters, for a URL path segment
+fn encode_path_segment(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,485 @@
+// Colours turned to gray for black-and-white print runs, instead of a
+// Ghostscript pass: the colour operators of the page contents (and of the
+// forms they draw) are replaced by their gray equivalent, and 8-bit RGB and
+// CMYK images are converted. Shadings, patterns and spot colours are left as
+// they are.
+
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageFormat;
+use lopdf::content::{Content, Operation};
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::HashSet;
+use std::path::Path;
+
+// What is turned to gray
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Grayscale {
+    // The inserted cover pages
+    #[default]
+    Cover,
+    // Every page of the output
+    All,
+}
+
+const JPEG_QUALITY: u8 = 90;
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+// Number of components of a colour space that is converted (RGB or CMYK,
+// device or calibrated), None for any other
+fn color_components(doc: &Document, space: &Object) -> Option<usize> {
+    match resolve(doc, space)? {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceRGB" | b"RGB" => Some(3),
+            b"DeviceCMYK" | b"CMYK" => Some(4),
+            _ => None,
+        },
+        Object::Array(array) => {
+            let family = array.first()?.as_name().ok()?;
+            match family {
+                b"CalRGB" => Some(3),
+                b"ICCBased" => {
+                    let profile = resolve(doc, array.get(1)?)?.as_stream().ok()?;
+                    match profile.dict.get(b"N").and_then(Object::as_i64).ok()? {
+                        3 => Some(3),
+                        4 => Some(4),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// Gray level of an RGB or CMYK colour, each component from 0 to 1
+fn gray(components: &[f32]) -> f32 {
+    match components {
+        [r, g, b] => 0.3 * r + 0.59 * g + 0.11 * b,
+        [c, m, y, k] => 1.0 - (0.3 * c + 0.59 * m + 0.11 * y + k).min(1.0),
+        _ => 0.0,
+    }
+}
+
+// Replaces the colour operators of one content stream after another, keeping
+// track of the colour spaces set
+#[derive(Default)]
+struct ContentConverter {
+    // Components of the current fill and stroke colour spaces, when converted
+    fill: Option<usize>,
+    stroke: Option<usize>,
+    saved: Vec<(Option<usize>, Option<usize>)>,
+}
+
+impl ContentConverter {
+    // `content` converted, None when it has no colour to convert or cannot be
+    // parsed. `resources` name the colour spaces it sets.
+    fn convert(
+        &mut self,
+        doc: &Document,
+        content: &[u8],
+        resources: Option<&Dictionary>,
+    ) -> Option<Vec<u8>> {
+        let mut content = Content::decode(content).ok()?;
+        let mut changed = false;
+        let numbers = |operands: &[Object]| -> Option<Vec<f32>> {
+            operands.iter().map(|o| o.as_float().ok()).collect()
+        };
+
+        for operation in &mut content.operations {
+            let converted = match operation.operator.as_str() {
+                "q" => {
+                    self.saved.push((self.fill, self.stroke));
+                    None
+                }
+                "Q" => {
+                    if let Some((fill, stroke)) = self.saved.pop() {
+                        self.fill = fill;
+                        self.stroke = stroke;
+                    }
+                    None
+                }
+                "rg" | "k" => numbers(&operation.operands).map(|c| ("g", vec![gray(&c).into()])),
+                "RG" | "K" => numbers(&operation.operands).map(|c| ("G", vec![gray(&c).into()])),
+                operator @ ("cs" | "CS") => {
+                    let space = operation.operands.first().and_then(|name| {
+                        let named = resources
+                            .and_then(|r| resolve(doc, r.get(b"ColorSpace").ok()?))
+                            .and_then(|spaces| spaces.as_dict().ok())
+                            .and_then(|spaces| spaces.get(name.as_name().ok()?).ok());
+                        color_components(doc, named.unwrap_or(name))
+                    });
+                    if operator == "cs" {
+                        self.fill = space;
+                    } else {
+                        self.stroke = space;
+                    }
+                    space.map(|_| (operator, vec![Object::Name(b"DeviceGray".to_vec())]))
+                }
+                operator @ ("sc" | "scn" | "SC" | "SCN") => {
+                    let current = if operator.starts_with('s') {
+                        self.fill
+                    } else {
+                        self.stroke
+                    };
+                    numbers(&operation.operands)
+                        .filter(|c| Some(c.len()) == current)
+                        .map(|c| (operator, vec![gray(&c).into()]))
+                }
+                _ => None,
+            };
+            if let Some((operator, operands)) = converted {
+                *operation = Operation::new(operator, operands);
+                changed = true;
+            }
+        }
+        if !changed {
+            return None;
+        }
+        content.encode().ok()
+    }
+}
+
+// `pixels` (8-bit RGB or CMYK samples) as 8-bit gray samples
+fn gray_samples(pixels: &[u8], components: usize) -> Vec<u8> {
+    pixels
+        .chunks_exact(components)
+        .map(|pixel| {
+            let levels: Vec<f32> = pixel.iter().map(|&v| v as f32 / 255.0).collect();
+            (gray(&levels) * 255.0).round() as u8
+        })
+        .collect()
+}
+
+// Convert the image `id` to gray in place, if it is an 8-bit RGB or CMYK
+// image without a decode array that can be read. JPEG images stay JPEG.
+fn convert_image(doc: &mut Document, id: ObjectId) -> Result<(), Box<dyn std::error::Error>> {
+    let components = {
+        let image = doc.get_object(id)?.as_stream()?;
+        let space = image.dict.get(b"ColorSpace")?;
+        let eight_bit = image
+            .dict
+            .get(b"BitsPerComponent")
+            .and_then(Object::as_i64)
+            .is_ok_and(|bits| bits == 8);
+        match color_components(doc, space) {
+            Some(components) if eight_bit && !image.dict.has(b"Decode") => components,
+            _ => return Ok(()),
+        }
+    };
+
+    let stream = doc.get_object_mut(id)?.as_stream_mut()?;
+    let jpeg = matches!(stream.filters().as_deref(), Ok([filter]) if filter == b"DCTDecode");
+    if jpeg {
+        // CMYK JPEGs are left alone
+        if components != 3 {
+            return Ok(());
+        }
+        // Gray levels as for the other images, not those of `to_luma8`
+        let decoded =
+            image::load_from_memory_with_format(&stream.content, ImageFormat::Jpeg)?.to_rgb8();
+        let (width, height) = decoded.dimensions();
+        let gray = image::GrayImage::from_raw(width, height, gray_samples(&decoded, 3))
+            .ok_or("image size does not match its samples")?;
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, JPEG_QUALITY).encode_image(&gray)?;
+        stream.set_content(encoded);
+    } else {
+        let samples = stream.get_plain_content()?;
+        stream.set_plain_content(gray_samples(&samples, components));
+        stream.compress()?;
+    }
+    stream.dict.set("ColorSpace", "DeviceGray");
+    Ok(())
+}
+
+// The images and forms drawn through `resources`
+fn xobjects(doc: &Document, resources: Option<&Dictionary>) -> Vec<ObjectId> {
+    resources
+        .and_then(|r| resolve(doc, r.get(b"XObject").ok()?))
+        .and_then(|xobjects| xobjects.as_dict().ok())
+        .map(|xobjects| {
+            xobjects
+                .iter()
+                .filter_map(|(_, xobject)| xobject.as_reference().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Turn the pages of `input` to gray, those `pages` picks by 0-based index,
+// writing the result to `output` (which may be `input`)
+pub(crate) fn convert_pages(
+    input: &Path,
+    output: &Path,
+    pages: impl Fn(usize) -> bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(input)?;
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+
+    // Content streams and forms with their new content, and the images, each
+    // converted once however many pages share them
+    let mut streams: Vec<(ObjectId, Vec<u8>)> = Vec::new();
+    let mut images = Vec::new();
+    let mut seen = HashSet::new();
+    for (index, &page_id) in page_ids.iter().enumerate() {
+        if !pages(index) {
+            continue;
+        }
+        let (resources, inherited) = doc.get_page_resources(page_id)?;
+        let resources = resources.or_else(|| {
+            inherited
+                .first()
+                .and_then(|id| doc.get_dictionary(*id).ok())
+        });
+
+        let mut converter = ContentConverter::default();
+        for id in doc.get_page_contents(page_id) {
+            if !seen.insert(id) {
+                continue;
+            }
+            let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) else {
+                continue;
+            };
+            let content = stream.get_plain_content()?;
+            if let Some(converted) = converter.convert(&doc, &content, resources) {
+                streams.push((id, converted));
+            }
+        }
+
+        // Forms are drawn with their own resources, or the page's
+        let mut pending: Vec<(ObjectId, Option<&Dictionary>)> = xobjects(&doc, resources)
+            .into_iter()
+            .map(|id| (id, resources))
+            .collect();
+        while let Some((id, outer)) = pending.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            let Ok(xobject) = doc.get_object(id).and_then(Object::as_stream) else {
+                continue;
+            };
+            match xobject.dict.get(b"Subtype").and_then(Object::as_name) {
+                Ok(b"Image") => images.push(id),
+                Ok(b"Form") => {
+                    let resources = xobject
+                        .dict
+                        .get(b"Resources")
+                        .ok()
+                        .and_then(|r| resolve(&doc, r)?.as_dict().ok())
+                        .or(outer);
+                    let content = xobject.get_plain_content()?;
+                    if let Some(converted) =
+                        ContentConverter::default().convert(&doc, &content, resources)
+                    {
+                        streams.push((id, converted));
+                    }
+                    pending.extend(
+                        xobjects(&doc, resources)
+                            .into_iter()
+                            .map(|id| (id, resources)),
+                    );
+                }
+                _ => {}
+            }
+        }
+    }
+
+    for (id, content) in streams {
+        let stream = doc.get_object_mut(id)?.as_stream_mut()?;
+        stream.set_plain_content(content);
+        stream.compress()?;
+    }
+    for id in images {
+        // An image that cannot be converted keeps its colours
+        let _ = convert_image(&mut doc, id);
+    }
+
+    doc.save(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use lopdf::{dictionary, Stream};
+    use std::fs;
+
+    // The operators of `content` with their numeric operands, or the names
+    // they take
+    fn operations(content: &[u8]) -> Vec<(String, Vec<String>)> {
+        Content::decode(content)
+            .unwrap()
+            .operations
+            .into_iter()
+            .map(|operation| {
+                let operands = operation
+                    .operands
+                    .iter()
+                    .map(|operand| match operand {
+                        Object::Name(name) => String::from_utf8_lossy(name).into_owned(),
+                        operand => format!("{:.2}", operand.as_float().unwrap()),
+                    })
+                    .collect();
+                (operation.operator, operands)
+            })
+            .collect()
+    }
+
+    fn convert(doc: &Document, content: &str, resources: Option<&Dictionary>) -> Option<String> {
+        let converted = ContentConverter::default().convert(doc, content.as_bytes(), resources)?;
+        let operations = operations(&converted)
+            .into_iter()
+            .map(|(operator, operands)| {
+                operands
+                    .into_iter()
+                    .chain(std::iter::once(operator))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect::<Vec<_>>();
+        Some(operations.join("; "))
+    }
+
+    #[test]
+    fn device_colours_become_gray() {
+        let doc = Document::with_version("1.7");
+        assert_eq!(
+            convert(&doc, "1 0 0 rg 0 0 1 RG 0 0 0 1 k 0 0 0 0 K", None).as_deref(),
+            Some("0.30 g; 0.11 G; 0.00 g; 1.00 G")
+        );
+    }
+
+    #[test]
+    fn colour_spaces_set_by_name() {
+        let mut doc = Document::with_version("1.7");
+        let profile = doc.add_object(Stream::new(dictionary! { "N" => 3 }, Vec::new()));
+        let resources = dictionary! {
+            "ColorSpace" => dictionary! {
+                "CS0" => vec![Object::Name(b"ICCBased".to_vec()), profile.into()],
+            },
+        };
+        assert_eq!(
+            convert(
+                &doc,
+                "/DeviceRGB cs 0 1 0 sc /CS0 CS 0 0 1 SCN",
+                Some(&resources)
+            )
+            .as_deref(),
+            Some("DeviceGray cs; 0.59 sc; DeviceGray CS; 0.11 SCN")
+        );
+        // Patterns and spot colours keep theirs
+        assert_eq!(convert(&doc, "/Pattern cs /P0 scn 0.5 g", None), None);
+    }
+
+    #[test]
+    fn restore_brings_back_the_saved_colour_space() {
+        let doc = Document::with_version("1.7");
+        assert_eq!(
+            convert(&doc, "/DeviceRGB cs q /Pattern cs 0.5 sc Q 1 0 0 sc", None).as_deref(),
+            Some("DeviceGray cs; q; Pattern cs; 0.50 sc; Q; 0.30 sc")
+        );
+    }
+
+    #[test]
+    fn gray_levels_of_samples() {
+        assert_eq!(
+            gray_samples(&[255, 0, 0, 255, 255, 255, 0, 0, 0], 3),
+            [77, 255, 0]
+        );
+        assert_eq!(gray_samples(&[0, 0, 0, 255, 0, 0, 0, 0], 4), [0, 255]);
+    }
+
+    #[test]
+    fn jpeg_images_stay_jpeg() {
+        let rgb = image::RgbImage::from_pixel(4, 4, image::Rgb([255, 0, 0]));
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 100)
+            .encode_image(&rgb)
+            .unwrap();
+        let mut doc = Document::with_version("1.7");
+        let id = doc.add_object(Stream::new(
+            dictionary! {
+                "Subtype" => "Image",
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+                "Filter" => "DCTDecode",
+            },
+            jpeg,
+        ));
+
+        convert_image(&mut doc, id).unwrap();
+        let image = doc.get_object(id).unwrap().as_stream().unwrap();
+        assert_eq!(
+            image.dict.get(b"ColorSpace").unwrap().as_name().unwrap(),
+            b"DeviceGray"
+        );
+        let decoded = image::load_from_memory_with_format(&image.content, ImageFormat::Jpeg)
+            .unwrap()
+            .to_luma8();
+        let levels: Vec<u8> = decoded.pixels().map(|pixel| pixel.0[0]).collect();
+        assert!(
+            levels.iter().all(|level| level.abs_diff(77) <= 3),
+            "{:?}",
+            levels
+        );
+    }
+
+    #[test]
+    fn converts_only_the_chosen_pages() {
+        let mut doc = fixtures::pdf(2);
+        let image = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 2,
+                "Height" => 1,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+            },
+            vec![255, 0, 0, 0, 0, 255],
+        ));
+        let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        for &page_id in &page_ids {
+            let content = doc.add_object(Stream::new(
+                dictionary! {},
+                b"1 0 0 rg 0 0 10 10 re f /Im0 Do".to_vec(),
+            ));
+            let page = doc.get_dictionary_mut(page_id).unwrap();
+            page.set("Contents", content);
+            page.set(
+                "Resources",
+                dictionary! { "XObject" => dictionary! { "Im0" => image } },
+            );
+        }
+        let dir = fixtures::scratch_dir("grayscale");
+        let path = dir.join("color.pdf");
+        doc.save(&path).unwrap();
+
+        convert_pages(&path, &path, |index| index == 0).unwrap();
+        let doc = Document::load(&path).unwrap();
+        let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let fill = |page_id| operations(&doc.get_page_content(page_id))[0].clone();
+        assert_eq!(
+            fill(page_ids[0]),
+            ("g".to_string(), vec!["0.30".to_string()])
+        );
+        assert_eq!(fill(page_ids[1]).0, "rg");
+
+        // The image the first page draws is converted, for both pages
+        let image = doc.get_object(image).unwrap().as_stream().unwrap();
+        assert_eq!(
+            image.dict.get(b"ColorSpace").unwrap().as_name().unwrap(),
+            b"DeviceGray"
+        );
+        assert_eq!(image.get_plain_content().unwrap(), [77, 28]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
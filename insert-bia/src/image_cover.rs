@@ -0,0 +1,75 @@
+// Cover pages given as images (PNG, JPEG or TIFF), turned into a one-page PDF
+// for qpdf to take the page from
+
+use crate::blank::{add_only_page, first_page_media_box, A4_MEDIA_BOX};
+use lopdf::{dictionary, xobject, Document};
+use std::path::Path;
+
+// What page the image is fitted to, keeping its proportions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ImageFit {
+    // An A4 page, whatever the target
+    #[default]
+    A4,
+    // The size of the target's first page
+    Target,
+}
+
+// Whether `path` names an image rather than a PDF, by its extension
+pub fn is_image(path: &Path) -> bool {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    matches!(
+        extension.as_deref(),
+        Some("png" | "jpg" | "jpeg" | "tif" | "tiff")
+    )
+}
+
+// Check that `image` can be read, so a broken cover stops the run before any
+// file is modified
+pub fn check_image(image: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    xobject::image(image)?;
+    Ok(())
+}
+
+// Write a one-page PDF to `output` with `image` scaled to fit the page and
+// centred on it. The page is A4 or, with `ImageFit::Target`, the size of
+// `target`'s first page (A4 when it cannot be read).
+pub fn write_image_page(
+    image: &Path,
+    fit: ImageFit,
+    target: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let media_box = match fit {
+        ImageFit::A4 => A4_MEDIA_BOX,
+        ImageFit::Target => first_page_media_box(target).unwrap_or(A4_MEDIA_BOX),
+    };
+    let stream = xobject::image(image)?;
+    let size = |key: &[u8]| -> Result<f32, Box<dyn std::error::Error>> {
+        Ok(stream.dict.get(key)?.as_i64()? as f32)
+    };
+    let (width, height) = (size(b"Width")?, size(b"Height")?);
+
+    let [x0, y0, x1, y1] = media_box;
+    let (page_width, page_height) = (x1 - x0, y1 - y0);
+    let scale = (page_width / width).min(page_height / height);
+    let (drawn_width, drawn_height) = (width * scale, height * scale);
+    let x = x0 + (page_width - drawn_width) / 2.0;
+    let y = y0 + (page_height - drawn_height) / 2.0;
+    let contents = format!(
+        "q {} 0 0 {} {} {} cm /Cover Do Q",
+        drawn_width, drawn_height, x, y
+    );
+
+    let mut doc = Document::with_version("1.5");
+    let image_id = doc.add_object(stream);
+    let resources = dictionary! {
+        "XObject" => dictionary! { "Cover" => image_id },
+    };
+    add_only_page(&mut doc, media_box, contents.into_bytes(), resources);
+    doc.save(output)?;
+    Ok(())
+}
@@ -0,0 +1,34 @@
+// Time of the last complete run over a directory, kept in a small file in
+// that directory so nightly runs can limit themselves to new arrivals.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LAST_RUN_FILE_NAME: &str = ".insert-bia-last-run";
+
+// None when no run over `dir` has completed yet
+pub fn read_last_run(dir: &Path) -> io::Result<Option<SystemTime>> {
+    let content = match fs::read_to_string(dir.join(LAST_RUN_FILE_NAME)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let seconds: u64 = content
+        .trim()
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok(Some(UNIX_EPOCH + Duration::from_secs(seconds)))
+}
+
+// Record `started` (the start of the run, so files arriving during it are
+// picked up next time) as the last run over `dir`
+pub fn write_last_run(dir: &Path, started: SystemTime) -> io::Result<()> {
+    let seconds = started
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    fs::write(dir.join(LAST_RUN_FILE_NAME), format!("{}\n", seconds))
+}
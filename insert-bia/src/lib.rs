@@ -0,0 +1,1627 @@
+pub mod attachments;
+pub mod backup;
+pub mod barcode;
+pub mod bates;
+pub mod blank;
+pub mod checkpoint;
+pub mod cleanup;
+pub mod database;
+pub mod fetch;
+#[cfg(test)]
+mod fixtures;
+pub mod footer;
+pub mod forms;
+pub mod google_sheets;
+pub mod grayscale;
+pub mod image_cover;
+pub mod last_run;
+pub mod lock;
+pub mod manifest;
+pub mod mapping;
+pub mod marker;
+pub mod matching;
+pub mod metadata;
+pub mod outline;
+pub mod pages;
+pub mod pdfa;
+pub mod position;
+pub mod qpdf;
+pub mod scan;
+pub mod script;
+pub mod signatures;
+pub mod space;
+pub mod template;
+pub mod watermark;
+
+use bates::Bates;
+use blank::BlankPage;
+use checkpoint::{checkpoint_key, load_checkpoint, Checkpoint, CheckpointRecord};
+use log::{debug, info, warn};
+use mapping::MappingEntry;
+use matching::{
+    candidate_keys, copy_of, find_key_in_text, match_fuzzy_key, match_numeric_id,
+    match_pdf_name_with_key, match_pdf_path_with_key, pages_in_name, CopyPolicy, MatchBy,
+    NameOptions, RuleStage, RuleTarget,
+};
+use position::Position;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+use template::CoverTemplate;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Processed,
+    Skipped,
+    // Carried the marker of an earlier run, left untouched
+    #[serde(rename = "already_processed")]
+    AlreadyProcessed,
+    // Online-only cloud file, left alone
+    Placeholder,
+    // Marked as excluded in the mapping
+    Excluded,
+    // Digitally signed, left alone so its signatures stay valid
+    Signed,
+    Error,
+}
+
+// What to do when files with the same name, found in different
+// directories, map to the same cover page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    // Process every copy
+    #[default]
+    All,
+    // Process only the first copy in path order
+    First,
+    // Refuse to start
+    Error,
+}
+
+// What to do with digitally signed files, whose signatures the inserted
+// pages would invalidate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignedPolicy {
+    // Leave them alone
+    #[default]
+    Skip,
+    // Process them, warning about each
+    Warn,
+    // Process them
+    Force,
+}
+
+// Mapped files sharing one file name
+#[derive(Debug, Clone)]
+pub struct Collision {
+    pub filename: String,
+    // Sorted; with DuplicatePolicy::First only the first is processed
+    pub paths: Vec<PathBuf>,
+}
+
+// A file that several Excel keys match, mapping it differently
+#[derive(Debug, Clone)]
+pub struct Ambiguity {
+    pub path: PathBuf,
+    // Sorted
+    pub keys: Vec<String>,
+}
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// Ask a running job to stop once the file in progress is done, e.g. from a
+// Ctrl+C handler
+pub fn request_stop() {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+pub fn stop_requested() -> bool {
+    STOP_REQUESTED.load(Ordering::SeqCst)
+}
+
+// Inputs of one insertion run
+#[derive(Debug, Clone)]
+pub struct JobConfig {
+    // Directories whose child directories contain the PDFs to process
+    pub dirs: Vec<PathBuf>,
+    pub excel_path: PathBuf,
+    // Format of `excel_path`; guessed from its extension when not set
+    pub mapping_format: Option<mapping::MappingFormat>,
+    pub sheet: mapping::SheetOptions,
+    // Password of an encrypted workbook
+    pub excel_password: Option<String>,
+    // Read the mapping from this Google Sheets URL or ID instead of
+    // `excel_path`, signing in with the service account key `google_credentials`
+    pub google_sheet: Option<String>,
+    pub google_credentials: Option<PathBuf>,
+    // Read the mapping from the rows `mapping_query` returns from this
+    // database instead of `excel_path`
+    pub mapping_db: Option<String>,
+    pub mapping_query: Option<String>,
+    pub bia_path: PathBuf,
+    pub scan: scan::ScanOptions,
+    // Process exactly these files instead of scanning `dirs`
+    pub files: Option<Vec<PathBuf>>,
+    // Take files and pages from this manifest, bypassing scanning, matching
+    // and the Excel file
+    pub manifest: Option<PathBuf>,
+    // Append each file's result to this JSON-lines file as it completes
+    pub checkpoint: Option<PathBuf>,
+    // Skip files the checkpoint already records as done
+    pub resume: bool,
+    // Copy each original here (mirroring the directory layout) before overwriting
+    pub backup_dir: Option<PathBuf>,
+    // Download online-only cloud files instead of skipping them
+    pub hydrate_placeholders: bool,
+    // Wait for a concurrent run on the same directory instead of failing
+    pub wait_lock: bool,
+    pub duplicates: DuplicatePolicy,
+    pub signed: SignedPolicy,
+    // Match files no rule matches to the most similar Excel key, if at least
+    // this similar (0 to 1)
+    pub fuzzy_threshold: Option<f64>,
+    // Regular expressions mapping file names to Excel keys or pages
+    pub rules: Vec<matching::MatchRule>,
+    // How loosely file names are compared with Excel keys
+    pub names: matching::NameOptions,
+    pub match_by: matching::MatchBy,
+    // Match files by the first run of at least this many digits in their
+    // name and the keys, when their names do not match
+    pub numeric_id: Option<usize>,
+    // Matching by text, the key is what this captures (its first group, or
+    // the whole match) in the first page's text
+    pub text_pattern: Option<regex::Regex>,
+    // Rhai script whose `match_file` decides before anything else what the
+    // files map to
+    pub match_script: Option<PathBuf>,
+    // Take the pages from the file names, as this captures them (its first
+    // group, or the whole match), bypassing the Excel file
+    pub page_pattern: Option<regex::Regex>,
+    // Read the mapping file again between files when it changes, so
+    // corrections made during a long run take effect
+    pub reload_mapping: bool,
+    // Finding no PDF files is not an error, as in a folder that is watched
+    pub allow_empty: bool,
+    // Take the pages of files in a folder with its own cover PDF (named like
+    // `bia_path`) from that one, unless the mapping names a cover
+    pub folder_covers: bool,
+    // Also insert a blank page the size of the file's first page, next to the
+    // cover pages or in place of them
+    pub blank_page: Option<BlankPage>,
+    // Page that cover images are fitted to
+    pub image_fit: image_cover::ImageFit,
+    // Generate each file's cover page from this template, filled in with its
+    // row, instead of taking it from `bia_path`
+    pub cover_template: Option<PathBuf>,
+    // Text stamped across every page of each processed file, filled in like
+    // the cover template
+    pub watermark: Option<String>,
+    // Number every page of the processed files, continuing across the run
+    pub bates: Option<Bates>,
+    // Number the original pages of each processed file at their foot,
+    // skipping the inserted ones, with this text ("Page {page} of {pages}")
+    pub page_numbers: Option<String>,
+    // Add a bookmark with this title for the inserted cover
+    pub bookmark: Option<String>,
+    // Draw form fields and annotations into the pages, so filled values can
+    // no longer be edited
+    pub flatten_forms: bool,
+    // Turn the inserted cover, or every page, to gray
+    pub grayscale: Option<grayscale::Grayscale>,
+    // Write the processed files linearized, for fast web view
+    pub linearize: bool,
+    // Recompress the processed files' streams and pack their objects, to
+    // keep them small
+    pub optimize: bool,
+    // Also recompress their images as JPEG where that is smaller
+    pub optimize_images: bool,
+    // PDF version ("1.7") the processed files are written as, and the lowest
+    // one they are written as
+    pub pdf_version: Option<String>,
+    pub min_pdf_version: Option<String>,
+    // Derive the document ID of the processed files from their contents, so
+    // the same inputs give byte-identical outputs
+    pub deterministic: bool,
+    // Encrypt the processed files with AES-256: the password needed to open
+    // them (or the one in their row), and the one needed to change them,
+    // which is the open password when not given
+    pub encrypt_password: Option<String>,
+    pub owner_password: Option<String>,
+    // Passwords tried in order on encrypted files, after the one in their row
+    pub passwords: Vec<String>,
+    // Encrypt the processed files again with the password that opened them
+    pub keep_password: bool,
+}
+
+impl JobConfig {
+    pub fn new(
+        dirs: Vec<PathBuf>,
+        excel_path: impl Into<PathBuf>,
+        bia_path: impl Into<PathBuf>,
+    ) -> Self {
+        JobConfig {
+            dirs,
+            excel_path: excel_path.into(),
+            mapping_format: None,
+            sheet: mapping::SheetOptions::default(),
+            excel_password: None,
+            google_sheet: None,
+            google_credentials: None,
+            mapping_db: None,
+            mapping_query: None,
+            bia_path: bia_path.into(),
+            scan: scan::ScanOptions::default(),
+            files: None,
+            manifest: None,
+            checkpoint: None,
+            resume: false,
+            backup_dir: None,
+            hydrate_placeholders: false,
+            wait_lock: false,
+            duplicates: DuplicatePolicy::default(),
+            signed: SignedPolicy::default(),
+            fuzzy_threshold: None,
+            rules: Vec::new(),
+            names: matching::NameOptions::default(),
+            match_by: matching::MatchBy::default(),
+            numeric_id: None,
+            text_pattern: None,
+            match_script: None,
+            page_pattern: None,
+            reload_mapping: false,
+            allow_empty: false,
+            folder_covers: false,
+            blank_page: None,
+            image_fit: image_cover::ImageFit::default(),
+            cover_template: None,
+            watermark: None,
+            bates: None,
+            page_numbers: None,
+            bookmark: None,
+            flatten_forms: false,
+            grayscale: None,
+            linearize: false,
+            optimize: false,
+            optimize_images: false,
+            pdf_version: None,
+            min_pdf_version: None,
+            deterministic: false,
+            encrypt_password: None,
+            owner_password: None,
+            passwords: Vec::new(),
+            keep_password: false,
+        }
+    }
+}
+
+// A job with its inputs loaded and the directory scanned, ready to execute
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub config: JobConfig,
+    pub bia_page_count: usize,
+    pub mappings: HashMap<String, MappingEntry>,
+    // How the mapping file's rows were read, None with a manifest
+    pub mapping_report: Option<mapping::MappingReport>,
+    pub pdf_files: Vec<PathBuf>,
+    // Insertions read from the manifest, by file, replacing `mappings`
+    pub manifest: Option<HashMap<PathBuf, Insertion>>,
+    // Mapped files found under the same name in several directories
+    pub collisions: Vec<Collision>,
+    // Files left out because the checkpoint marks them as done
+    pub already_done: usize,
+    // Excel keys used by files completed in earlier runs
+    resumed_keys: HashSet<String>,
+    // Bates number of the first page this run numbers
+    pub next_bates: u64,
+    // Page counts of the per-entry covers of the mapping file
+    cover_page_counts: HashMap<PathBuf, usize>,
+    // Names read from the files themselves when matching by title, text or
+    // barcode
+    document_names: HashMap<PathBuf, String>,
+    // What the match script maps files to, with the key or the script's name
+    script_matches: HashMap<PathBuf, (MappingEntry, String)>,
+    pub template: Option<CoverTemplate>,
+    // The cover PDF in each file's folder, for the files that have one
+    folder_covers: HashMap<PathBuf, PathBuf>,
+    // Keys picked for ambiguous files (see `ambiguities`), None for files to
+    // leave alone
+    pub choices: HashMap<PathBuf, Option<String>>,
+}
+
+// What goes into one file
+#[derive(Debug, Clone)]
+pub struct Insertion {
+    // Excel key (or manifest path) that selected it
+    pub key: String,
+    // 1-based bia.pdf pages, in the order they are inserted
+    pub pages: Vec<u32>,
+    pub position: Position,
+    // Cover PDF the page comes from, None for the job's bia.pdf
+    pub cover: Option<PathBuf>,
+    // File name the processed file is renamed to
+    pub output_name: Option<String>,
+    // Excluded in the mapping: the file is matched but left alone
+    pub excluded: bool,
+    // 1-based pages of the file to delete in the same pass
+    pub remove_pages: Vec<u32>,
+    // Title, author, subject and keywords to set on the file
+    pub info: metadata::DocumentInfo,
+    // Password to encrypt the file with, instead of the job's
+    pub encrypt_password: Option<String>,
+    // Password that opens the file, tried before the job's
+    pub password: Option<String>,
+    // The cells of the file's row, for the cover template
+    pub fields: HashMap<String, String>,
+    // Similarity of a fuzzy match, None when the name matched by the rules
+    pub similarity: Option<f64>,
+}
+
+impl Job {
+    // The insertion planned for `pdf_path`, None when nothing maps to it
+    pub fn lookup(&self, pdf_path: &Path) -> Option<Insertion> {
+        let mut insertion = self.lookup_mapping(pdf_path)?;
+        if self.template.is_some() {
+            // The template's one page replaces the mapped cover pages
+            insertion.pages = vec![1];
+            insertion.cover = None;
+        } else if insertion.cover.is_none() {
+            insertion.cover = self.folder_covers.get(pdf_path).cloned();
+        }
+        if self.config.blank_page == Some(BlankPage::Instead) {
+            insertion.pages.clear();
+        }
+        Some(insertion)
+    }
+
+    // The insertion the manifest, the file name or the mapping gives
+    // `pdf_path`, before its folder's cover applies
+    fn lookup_mapping(&self, pdf_path: &Path) -> Option<Insertion> {
+        if let Some(manifest) = &self.manifest {
+            return manifest.get(pdf_path).cloned();
+        }
+        if let Some(pattern) = &self.config.page_pattern {
+            let filename = pdf_path.file_name()?.to_str()?;
+            let (pages, key) = pages_in_name(filename, pattern)?;
+            return Some(Insertion {
+                key,
+                pages,
+                position: self.config.sheet.default_position,
+                cover: None,
+                output_name: None,
+                excluded: false,
+                remove_pages: Vec::new(),
+                info: metadata::DocumentInfo::default(),
+                encrypt_password: None,
+                password: None,
+                fields: HashMap::new(),
+                similarity: None,
+            });
+        }
+
+        // Keys with a folder ("customerA/hoa.pdf") are more specific than
+        // the bare filename
+        let relative = scan::relative_path(pdf_path, &self.config.dirs);
+        let matched_name = self.matched_name(pdf_path)?;
+        let filename = matched_name.as_str();
+        let mut similarity = None;
+        // A key chosen for the file among several candidates, or None to skip it
+        let chosen = self.choices.get(pdf_path).map(|choice| {
+            let key = choice.as_ref()?;
+            Some((self.mappings.get(key)?.clone(), key.clone()))
+        });
+        let (entry, key) = match chosen.unwrap_or_else(|| {
+            self.script_matches
+                .get(pdf_path)
+                .cloned()
+                .or_else(|| self.match_rules(filename, RuleStage::Before))
+                .or_else(|| match_pdf_path_with_key(&relative, &self.mappings))
+                .or_else(|| match_pdf_name_with_key(filename, &self.mappings, &self.config.names))
+                .or_else(|| {
+                    let min_digits = self.config.numeric_id?;
+                    match_numeric_id(filename, &self.mappings, min_digits)
+                })
+                .or_else(|| self.match_rules(filename, RuleStage::After))
+        }) {
+            Some(found) => found,
+            None if self.choices.contains_key(pdf_path) => return None,
+            None => {
+                let threshold = self.config.fuzzy_threshold?;
+                let (entry, key, score) = match_fuzzy_key(filename, &self.mappings, threshold)?;
+                similarity = Some(score);
+                (entry, key)
+            }
+        };
+        // Rows with dates apply by the file's modification date
+        let entry = if entry.dated.is_empty() {
+            entry
+        } else {
+            let modified = pdf_path.metadata().and_then(|m| m.modified()).ok();
+            entry.on_date(modified)?
+        };
+        Some(Insertion {
+            key,
+            pages: entry.pages,
+            position: entry.position,
+            cover: entry.cover,
+            output_name: entry.output_name,
+            excluded: entry.excluded,
+            remove_pages: entry.remove_pages,
+            info: entry.info,
+            encrypt_password: entry.encrypt_password,
+            password: entry.password,
+            fields: entry.fields,
+            similarity,
+        })
+    }
+
+    // Files the name matching decided between several keys that map them
+    // differently, leaving out those with a choice already made. Which key
+    // wins is well defined, but rarely what was meant.
+    pub fn ambiguities(&self) -> Vec<Ambiguity> {
+        if self.manifest.is_some() || self.config.page_pattern.is_some() {
+            return Vec::new();
+        }
+
+        let mut ambiguities = Vec::new();
+        for pdf_path in &self.pdf_files {
+            if self.choices.contains_key(pdf_path) {
+                continue;
+            }
+            let (Some(name), Some(insertion)) =
+                (self.matched_name(pdf_path), self.lookup(pdf_path))
+            else {
+                continue;
+            };
+            let keys = candidate_keys(&name, &self.mappings, &self.config.names);
+            // Rules and path keys take precedence over the candidates
+            if !keys.contains(&insertion.key) {
+                continue;
+            }
+            let first = &self.mappings[&keys[0]];
+            if keys.iter().any(|key| self.mappings[key] != *first) {
+                ambiguities.push(Ambiguity {
+                    path: pdf_path.clone(),
+                    keys,
+                });
+            }
+        }
+        ambiguities
+    }
+
+    // The name matched against the Excel keys for `pdf_path`: its file name,
+    // its folder's name or its document title
+    pub fn matched_name(&self, pdf_path: &Path) -> Option<String> {
+        match self.config.match_by {
+            MatchBy::Name => pdf_path.file_name()?.to_str().map(str::to_string),
+            // Files directly in a scanned directory have no folder of their own
+            MatchBy::Folder => scan::relative_path(pdf_path, &self.config.dirs)
+                .parent()?
+                .file_name()?
+                .to_str()
+                .map(str::to_string),
+            MatchBy::Title | MatchBy::Text | MatchBy::Barcode => {
+                self.document_names.get(pdf_path).cloned()
+            }
+        }
+    }
+
+    // The entry the first matching rule of `stage` gives `filename`, with the
+    // Excel key, or the rule's pattern for a rule with pages, and the cover,
+    // position and output name the rule sets. A rule naming a key the Excel
+    // file does not have is passed over.
+    fn match_rules(&self, filename: &str, stage: RuleStage) -> Option<(MappingEntry, String)> {
+        self.config
+            .rules
+            .iter()
+            .filter(|rule| rule.stage == stage)
+            .find_map(|rule| {
+                let (mut entry, key) = match rule.apply(filename)? {
+                    RuleTarget::Key(key) => {
+                        match_pdf_name_with_key(&key, &self.mappings, &self.config.names)?
+                    }
+                    RuleTarget::Pages(pages) => (
+                        MappingEntry {
+                            position: self.config.sheet.default_position,
+                            ..MappingEntry::new(pages)
+                        },
+                        rule.pattern.to_string(),
+                    ),
+                };
+                if let Some(cover) = &rule.cover {
+                    entry.cover = Some(cover.clone());
+                }
+                if let Some(position) = rule.position {
+                    entry.position = position;
+                }
+                if let Some(output_name) = rule.expand_output_name(filename) {
+                    entry.output_name = Some(output_name);
+                }
+                Some((entry, key))
+            })
+    }
+
+    // The cover PDF of `insertion` and its page count
+    pub fn cover_of<'a>(&'a self, insertion: &'a Insertion) -> (&'a Path, usize) {
+        match &insertion.cover {
+            Some(cover) => (cover, self.cover_page_counts[cover]),
+            None => (&self.config.bia_path, self.bia_page_count),
+        }
+    }
+
+    // What executing the job would do, without touching any file
+    pub fn preview(&self) -> PreviewResults {
+        let mut will_process = 0;
+        let mut will_skip = 0;
+        let mut will_exclude = 0;
+        let mut used_mappings = HashSet::new();
+
+        // Analyze each PDF to see if it will be processed
+        for pdf_path in &self.pdf_files {
+            match self.lookup(pdf_path) {
+                Some(insertion) if insertion.excluded => will_exclude += 1,
+                Some(insertion) => {
+                    will_process += 1;
+                    used_mappings.insert(insertion.key);
+                }
+                None => will_skip += 1,
+            }
+        }
+
+        // Find unmapped Excel entries; excluded ones are not expected to match
+        let mut unmapped_excel_entries = Vec::new();
+        for (excel_name, entry) in &self.mappings {
+            if !entry.excluded && !used_mappings.contains(excel_name) {
+                unmapped_excel_entries.push(excel_name.clone());
+            }
+        }
+        let unmapped_entries = unmapped_excel_entries.len();
+
+        // Sort for consistent output
+        unmapped_excel_entries.sort();
+
+        PreviewResults {
+            will_process,
+            will_skip,
+            will_exclude,
+            unmapped_entries,
+            unmapped_excel_entries,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub path: PathBuf,
+    pub status: FileStatus,
+    // Excel key the file matched
+    pub key: Option<String>,
+    // 1-based bia.pdf pages that were inserted (or found already inserted)
+    pub pages: Vec<u32>,
+    pub error: Option<String>,
+    // qpdf warnings for a successfully processed file
+    pub qpdf_warnings: Option<String>,
+    // Where the original was backed up
+    pub backup: Option<PathBuf>,
+    // New path of a file renamed after processing
+    pub renamed: Option<PathBuf>,
+    // Bates numbers of its first and last pages
+    pub bates: Option<(u64, u64)>,
+    // Why the output of a PDF/A file no longer conforms
+    pub pdfa_lost: Option<String>,
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct JobSummary {
+    pub processed: usize,
+    pub skipped: usize,
+    pub already_processed: usize,
+    pub placeholders: usize,
+    pub excluded: usize,
+    pub signed: usize,
+    pub errors: usize,
+    // Stopped by `request_stop` before every file was handled
+    pub interrupted: bool,
+    // Times the mapping file was read again after it changed
+    pub mapping_reloads: usize,
+    // Excel entries that no PDF matched, sorted; left empty when interrupted
+    pub unmapped_excel_entries: Vec<String>,
+    pub files: Vec<FileResult>,
+}
+
+#[derive(Debug)]
+pub struct PreviewResults {
+    pub will_process: usize,
+    pub will_skip: usize,
+    pub will_exclude: usize,
+    pub unmapped_entries: usize,
+    pub unmapped_excel_entries: Vec<String>,
+}
+
+// Check qpdf, load bia.pdf and the mapping, and scan the directory. Nothing
+// is modified; errors here mean the job cannot start at all.
+pub fn prepare(mut config: JobConfig) -> Result<Job, Box<dyn std::error::Error>> {
+    if !qpdf::check_qpdf_installed() {
+        return Err("qpdf is not installed or not in PATH".into());
+    }
+    if config.match_by == MatchBy::Barcode {
+        barcode::check_tools_installed()?;
+    }
+
+    // Validate required files exist
+    let needs_mapping = config.manifest.is_none() && config.page_pattern.is_none();
+    if needs_mapping && !config.excel_path.exists() {
+        return Err(format!("Excel file not found: {}", config.excel_path.display()).into());
+    }
+
+    let template = match &config.cover_template {
+        Some(path) => Some(CoverTemplate::load(path)?),
+        None => None,
+    };
+    // A template needs the cells of the rows, and so may the watermark
+    config.sheet.keep_fields |= template.is_some()
+        || config
+            .watermark
+            .as_deref()
+            .is_some_and(template::uses_fields);
+    if template.is_none() && !config.bia_path.exists() {
+        return Err(format!("Cover PDF not found: {}", config.bia_path.display()).into());
+    }
+
+    if config.dirs.is_empty() {
+        return Err("No directory given".into());
+    }
+
+    for dir in &config.dirs {
+        if !dir.is_dir() {
+            return Err(format!("Directory does not exist: {}", dir.display()).into());
+        }
+    }
+
+    // Backups of several directories are kept in subdirectories named after them
+    if config.backup_dir.is_some() && config.dirs.len() > 1 {
+        let mut names = HashSet::new();
+        for dir in &config.dirs {
+            if !names.insert(dir.file_name()) {
+                return Err(format!(
+                    "Directories backed up together need distinct names: {}",
+                    dir.display()
+                )
+                .into());
+            }
+        }
+    }
+
+    if config.resume && config.checkpoint.is_none() {
+        return Err("Resuming requires a checkpoint file".into());
+    }
+
+    // The template stands in for bia.pdf with its one page
+    let bia_page_count = match template {
+        Some(_) => 1,
+        None => cover_page_count(&config.bia_path).map_err(|e| {
+            format!(
+                "Failed to get page count from {}: {}",
+                config.bia_path.display(),
+                e
+            )
+        })?,
+    };
+
+    let mut mappings = HashMap::new();
+    let mut mapping_report = None;
+    let mut manifest = None;
+    if let Some(manifest_path) = &config.manifest {
+        info!("Reading manifest {}...", manifest_path.display());
+        let entries = manifest::read_manifest(manifest_path)
+            .map_err(|e| format!("Failed to read {}: {}", manifest_path.display(), e))?;
+
+        // Keys show the paths as written in the manifest
+        let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+        let mut insertions = HashMap::new();
+        for entry in entries {
+            let key = entry
+                .pdf_path
+                .strip_prefix(manifest_dir)
+                .unwrap_or(&entry.pdf_path);
+            let insertion = Insertion {
+                key: key.display().to_string(),
+                pages: entry.bia_pages,
+                position: entry.position,
+                cover: None,
+                output_name: None,
+                excluded: false,
+                remove_pages: Vec::new(),
+                info: metadata::DocumentInfo::default(),
+                encrypt_password: None,
+                password: None,
+                fields: HashMap::new(),
+                similarity: None,
+            };
+            insertions.insert(entry.pdf_path, insertion);
+        }
+        info!("Found {} entries in manifest", insertions.len());
+        manifest = Some(insertions);
+    } else if config.page_pattern.is_some() {
+        info!("Taking the pages from the file names");
+    } else {
+        let (read, report) = read_mapping(&config)?;
+        mappings = read;
+        mapping_report = Some(report);
+    }
+
+    let mut cover_page_counts = HashMap::new();
+    let rule_covers = config.rules.iter().filter_map(|rule| rule.cover.as_ref());
+    // Every configured type's cover is checked, whether or not a row uses it
+    let type_covers = config.sheet.type_covers.values();
+    for cover in mapping_covers(&mappings)
+        .chain(rule_covers)
+        .chain(type_covers)
+    {
+        count_cover_pages(cover, &mut cover_page_counts)?;
+    }
+
+    let mut job = Job {
+        config,
+        bia_page_count,
+        mappings,
+        mapping_report,
+        pdf_files: Vec::new(),
+        manifest,
+        collisions: Vec::new(),
+        already_done: 0,
+        resumed_keys: HashSet::new(),
+        next_bates: 0,
+        cover_page_counts,
+        document_names: HashMap::new(),
+        script_matches: HashMap::new(),
+        template,
+        folder_covers: HashMap::new(),
+        choices: HashMap::new(),
+    };
+    rescan(&mut job)?;
+    Ok(job)
+}
+
+// Scan the directory again (or check the listed files), and redo what
+// depends on the files found. A job that is run more than once, as `watch`
+// does, is prepared once and rescanned before each run.
+pub fn rescan(job: &mut Job) -> Result<(), Box<dyn std::error::Error>> {
+    let config = &job.config;
+    let listed_files = match &job.manifest {
+        Some(insertions) => {
+            let mut files: Vec<PathBuf> = insertions.keys().cloned().collect();
+            files.sort();
+            Some(files)
+        }
+        None => config.files.clone(),
+    };
+
+    let mut pdf_files = match &listed_files {
+        Some(files) => {
+            let mut pdf_files = Vec::new();
+            for path in files {
+                if path.is_file() {
+                    pdf_files.push(path.clone());
+                } else {
+                    warn!("Not a file, ignoring: {}", path.display());
+                }
+            }
+            if pdf_files.is_empty() {
+                return Err("None of the listed files exist".into());
+            }
+            info!("Processing {} listed PDF files", pdf_files.len());
+            pdf_files
+        }
+        None => {
+            // Scan child directories for PDF files
+            let pdf_files = scan::scan_directories(&config.dirs, &config.scan)
+                .map_err(|e| format!("Failed to scan directories: {}", e))?;
+
+            // With a time filter, finding nothing new is a normal outcome
+            let time_filtered = config.scan.modified_since.is_some() || config.scan.since_last_run;
+            if pdf_files.is_empty() && !time_filtered && !config.allow_empty {
+                return Err("No PDF files found in child directories!".into());
+            }
+
+            info!("Found {} PDF files in subdirectories", pdf_files.len());
+            pdf_files
+        }
+    };
+
+    let folder_covers = if config.folder_covers {
+        find_folder_covers(config, &mut pdf_files)
+    } else {
+        HashMap::new()
+    };
+    for cover in folder_covers.values() {
+        count_cover_pages(cover, &mut job.cover_page_counts)?;
+    }
+
+    // Skip files already completed by a previous (interrupted) run
+    let mut already_done = 0;
+    let mut resumed_keys = HashSet::new();
+    let mut next_bates = config.bates.as_ref().map_or(0, |bates| bates.start);
+    if let (true, Some(checkpoint_path)) = (config.resume, &config.checkpoint) {
+        let records = load_checkpoint(checkpoint_path)
+            .map_err(|e| format!("Failed to read checkpoint: {}", e))?;
+
+        // Files that failed or were not downloaded are retried on resume
+        let completed: HashSet<String> = records
+            .iter()
+            .filter(|r| !matches!(r.status, FileStatus::Error | FileStatus::Placeholder))
+            .map(|r| r.file.clone())
+            .collect();
+        // Numbering continues after the last page numbered before
+        if let Some(bates) = &config.bates {
+            let last = records
+                .iter()
+                .filter_map(|r| bates.last_number(r.bates.as_deref()?))
+                .max();
+            if let Some(last) = last {
+                next_bates = last + 1;
+            }
+        }
+        resumed_keys.extend(records.into_iter().filter_map(|r| r.key));
+
+        let before = pdf_files.len();
+        pdf_files.retain(|pdf_path| !completed.contains(&checkpoint_key(pdf_path)));
+        already_done = before - pdf_files.len();
+    }
+
+    // Read once here; lookup is called for every file several times
+    let document_names = read_document_names(config, &pdf_files, &job.mappings);
+
+    let script_matches = match &config.match_script {
+        Some(path) => run_match_script(path, config, &pdf_files, &job.mappings)?,
+        None => HashMap::new(),
+    };
+    for (entry, _) in script_matches.values() {
+        if let Some(cover) = &entry.cover {
+            count_cover_pages(cover, &mut job.cover_page_counts)?;
+        }
+    }
+
+    job.pdf_files = pdf_files;
+    job.already_done = already_done;
+    job.resumed_keys = resumed_keys;
+    job.next_bates = next_bates;
+    job.document_names = document_names;
+    job.script_matches = script_matches;
+    job.folder_covers = folder_covers;
+
+    job.collisions = find_collisions(job);
+    match job.config.duplicates {
+        DuplicatePolicy::All => {}
+        DuplicatePolicy::First => {
+            let later_copies: HashSet<&PathBuf> = job
+                .collisions
+                .iter()
+                .flat_map(|collision| collision.paths.iter().skip(1))
+                .collect();
+            let pdf_files = job
+                .pdf_files
+                .iter()
+                .filter(|pdf_path| !later_copies.contains(pdf_path))
+                .cloned()
+                .collect();
+            job.pdf_files = pdf_files;
+        }
+        DuplicatePolicy::Error if !job.collisions.is_empty() => {
+            let names: Vec<&str> = job.collisions.iter().map(|c| c.filename.as_str()).collect();
+            return Err(format!(
+                "The same file name was found in several directories: {}",
+                names.join(", ")
+            )
+            .into());
+        }
+        DuplicatePolicy::Error => {}
+    }
+
+    if job.config.names.copies == CopyPolicy::Error {
+        let copies = find_copies(job);
+        if !copies.is_empty() {
+            return Err(format!(
+                "Copies of mapped files were found: {} (remove them or choose another --copies policy)",
+                copies.join(", ")
+            )
+            .into());
+        }
+    }
+
+    Ok(())
+}
+
+// Read the mapping from the database, Google Sheet or file the config names
+fn read_mapping(
+    config: &JobConfig,
+) -> Result<(HashMap<String, MappingEntry>, mapping::MappingReport), Box<dyn std::error::Error>> {
+    let cover_dir = config.bia_path.parent().unwrap_or(Path::new(""));
+    if let Some(url) = &config.mapping_db {
+        let shown = database::display_url(url);
+        info!("Querying {}...", shown);
+        let query = config
+            .mapping_query
+            .as_deref()
+            .ok_or("A query is needed to read the mapping from a database")?;
+        let (mappings, report) =
+            database::read_database_mappings(url, query, &config.sheet, cover_dir)
+                .map_err(|e| format!("Failed to read the mapping from {}: {}", shown, e))?;
+
+        info!("Found {} mappings in the database", mappings.len());
+        Ok((mappings, report))
+    } else if let Some(sheet) = &config.google_sheet {
+        info!("Reading Google Sheet {}...", sheet);
+        let credentials = config
+            .google_credentials
+            .as_deref()
+            .ok_or("A service account key is needed to read a Google Sheet")?;
+        let (mappings, report) =
+            google_sheets::read_google_sheet(sheet, credentials, &config.sheet, cover_dir)
+                .map_err(|e| format!("Failed to read Google Sheet {}: {}", sheet, e))?;
+
+        info!("Found {} mappings in Google Sheet", mappings.len());
+        Ok((mappings, report))
+    } else {
+        info!("Reading {}...", config.excel_path.display());
+        let (mappings, report) = mapping::read_mappings(
+            &config.excel_path,
+            config.mapping_format,
+            &config.sheet,
+            cover_dir,
+            config.excel_password.as_deref(),
+        )
+        .map_err(|e| format!("Failed to read {}: {}", config.excel_path.display(), e))?;
+
+        info!("Found {} mappings in Excel file", mappings.len());
+        Ok((mappings, report))
+    }
+}
+
+// Record the page count of `cover`, failing when it cannot be read
+fn count_cover_pages(
+    cover: &Path,
+    cover_page_counts: &mut HashMap<PathBuf, usize>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if cover_page_counts.contains_key(cover) {
+        return Ok(());
+    }
+    if !cover.exists() {
+        return Err(format!("Cover PDF not found: {}", cover.display()).into());
+    }
+    let page_count = cover_page_count(cover)
+        .map_err(|e| format!("Failed to get page count from {}: {}", cover.display(), e))?;
+    cover_page_counts.insert(cover.to_path_buf(), page_count);
+    Ok(())
+}
+
+// Pages of a cover PDF, or 1 for a cover image that can be read
+fn cover_page_count(cover: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    if image_cover::is_image(cover) {
+        image_cover::check_image(cover)?;
+        return Ok(1);
+    }
+    qpdf::get_pdf_page_count(cover)
+}
+
+// Ask the match script about every file, resolving the keys it returns.
+// A script error stops the job before anything is modified.
+fn run_match_script(
+    path: &Path,
+    config: &JobConfig,
+    pdf_files: &[PathBuf],
+    mappings: &HashMap<String, MappingEntry>,
+) -> Result<HashMap<PathBuf, (MappingEntry, String)>, Box<dyn std::error::Error>> {
+    let script = script::MatchScript::load(path, mappings)
+        .map_err(|e| format!("Failed to load match script {}", e))?;
+    let script_name = path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned();
+    let cover_dir = config.bia_path.parent().unwrap_or(Path::new(""));
+
+    let mut matches = HashMap::new();
+    for pdf_path in pdf_files {
+        let relative = scan::relative_path(pdf_path, &config.dirs);
+        let filename = pdf_path.file_name().unwrap_or_default().to_string_lossy();
+        let folder = relative
+            .parent()
+            .and_then(Path::file_name)
+            .unwrap_or_default()
+            .to_string_lossy();
+        let decision = script
+            .decide(&filename, &folder)
+            .map_err(|e| format!("Match script failed for {}", e))?;
+
+        let (mut entry, key, overrides) = match decision {
+            None => continue,
+            Some(script::ScriptDecision::Key(key, overrides)) => {
+                let (entry, key) = match_pdf_name_with_key(&key, mappings, &config.names)
+                    .ok_or_else(|| {
+                        format!(
+                            "Match script gave {} the key \"{}\", which is not in the mapping",
+                            relative.display(),
+                            key
+                        )
+                    })?;
+                (entry, key, overrides)
+            }
+            Some(script::ScriptDecision::Pages(pages, overrides)) => {
+                let entry = MappingEntry {
+                    position: config.sheet.default_position,
+                    ..MappingEntry::new(pages)
+                };
+                (entry, script_name.clone(), overrides)
+            }
+        };
+        if let Some(cover) = overrides.cover {
+            entry.cover = Some(cover_dir.join(cover));
+        }
+        if let Some(position) = overrides.position {
+            entry.position = position;
+        }
+        if let Some(output_name) = overrides.output_name {
+            entry.output_name = Some(output_name);
+        }
+        info!(
+            "{} matched by {} ({})",
+            pdf_path.display(),
+            script_name,
+            key
+        );
+        matches.insert(pdf_path.clone(), (entry, key));
+    }
+    Ok(matches)
+}
+
+// What each file is matched by when that is read from the file: its title,
+// the name its first page's text gives (what `text_pattern` captures, or the
+// longest key the text contains), or a barcode on its first page
+fn read_document_names(
+    config: &JobConfig,
+    pdf_files: &[PathBuf],
+    mappings: &HashMap<String, MappingEntry>,
+) -> HashMap<PathBuf, String> {
+    let mut names = HashMap::new();
+    if matches!(config.match_by, MatchBy::Name | MatchBy::Folder) {
+        return names;
+    }
+
+    for pdf_path in pdf_files {
+        let name = match config.match_by {
+            MatchBy::Name | MatchBy::Folder => None,
+            MatchBy::Title => metadata::read_title(pdf_path),
+            MatchBy::Text => metadata::read_first_page_text(pdf_path)
+                .and_then(|text| text_name(&text, config, mappings)),
+            MatchBy::Barcode => match barcode::read_barcodes(pdf_path) {
+                // With several codes on the page, the one that is a key
+                Ok(codes) => codes
+                    .iter()
+                    .find(|code| match_pdf_name_with_key(code, mappings, &config.names).is_some())
+                    .or(codes.first())
+                    .cloned(),
+                Err(e) => {
+                    warn!("Could not read barcodes of {}: {}", pdf_path.display(), e);
+                    None
+                }
+            },
+        };
+        match name {
+            Some(name) => {
+                info!("{} reads as \"{}\"", pdf_path.display(), name);
+                names.insert(pdf_path.clone(), name);
+            }
+            None => info!("{} has nothing to match by", pdf_path.display()),
+        }
+    }
+    info!(
+        "Read what to match by from {} of {} files",
+        names.len(),
+        pdf_files.len()
+    );
+    names
+}
+
+// What `text_pattern` captures in a page's text (its first group, or the
+// whole match), or the longest key in the text without a pattern
+fn text_name(
+    text: &str,
+    config: &JobConfig,
+    mappings: &HashMap<String, MappingEntry>,
+) -> Option<String> {
+    match &config.text_pattern {
+        Some(pattern) => {
+            let captures = pattern.captures(text)?;
+            let found = captures.get(1).or_else(|| captures.get(0))?;
+            Some(found.as_str().trim().to_string())
+        }
+        None => find_key_in_text(text, mappings, &config.names),
+    }
+}
+
+// Names of the files that map only as a copy of a mapped file ("hoa (1).pdf"
+// when the Excel file has "hoa")
+fn find_copies(job: &Job) -> Vec<String> {
+    let originals_only = NameOptions {
+        copies: CopyPolicy::Original,
+        ..job.config.names
+    };
+    let mut copies: Vec<String> = job
+        .pdf_files
+        .iter()
+        .filter_map(|pdf_path| job.matched_name(pdf_path))
+        .filter(|filename| {
+            copy_of(filename, job.config.names.copy_suffixes).is_some()
+                && match_pdf_name_with_key(filename, &job.mappings, &originals_only).is_none()
+                && match_pdf_name_with_key(filename, &job.mappings, &job.config.names).is_some()
+        })
+        .collect();
+    copies.sort();
+    copies.dedup();
+    copies
+}
+
+// Group mapped files by (case-insensitive) file name and keep the groups
+// with more than one file
+fn find_collisions(job: &Job) -> Vec<Collision> {
+    // Files matched by different keys (such as "customerA/hoa" and
+    // "customerB/hoa") are told apart by the mapping
+    let mut by_name: HashMap<(String, String), Vec<PathBuf>> = HashMap::new();
+    for pdf_path in &job.pdf_files {
+        let Some(insertion) = job.lookup(pdf_path) else {
+            continue;
+        };
+        if insertion.excluded {
+            continue;
+        }
+        if let Some(filename) = pdf_path.file_name().and_then(|n| n.to_str()) {
+            by_name
+                .entry((filename.to_lowercase(), insertion.key))
+                .or_default()
+                .push(pdf_path.clone());
+        }
+    }
+
+    let mut collisions: Vec<Collision> = by_name
+        .into_values()
+        .filter(|paths| paths.len() > 1)
+        .map(|mut paths| {
+            paths.sort();
+            let filename = paths[0]
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or_default()
+                .to_string();
+            Collision { filename, paths }
+        })
+        .collect();
+    collisions.sort_by(|a, b| a.filename.cmp(&b.filename));
+    collisions
+}
+
+// Process every file of a prepared job, calling `on_file` as each one completes
+pub fn execute(
+    job: &Job,
+    mut on_file: impl FnMut(&FileResult),
+) -> Result<JobSummary, Box<dyn std::error::Error>> {
+    let run_started = SystemTime::now();
+
+    // Held until every file is done
+    let _locks = lock::acquire_all(&job.config.dirs, job.config.wait_lock)?;
+
+    space::check_disk_space(job)?;
+
+    let mut checkpoint = match &job.config.checkpoint {
+        Some(path) => Some(
+            Checkpoint::open(path, job.config.resume)
+                .map_err(|e| format!("Failed to open checkpoint {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+
+    // Process PDFs and track which Excel entries were used
+    let mut summary = JobSummary::default();
+    let mut used_mappings = job.resumed_keys.clone();
+    let mut next_bates = job.next_bates;
+
+    // The job with the mapping as last read, once it has changed
+    let mut reloaded: Option<Job> = None;
+    let mut mapping_modified = if job.config.reload_mapping {
+        mapping_file_modified(&job.config)
+    } else {
+        None
+    };
+
+    for pdf_path in &job.pdf_files {
+        if stop_requested() {
+            summary.interrupted = true;
+            break;
+        }
+
+        if let Some(seen) = mapping_modified {
+            let modified = mapping_file_modified(&job.config).filter(|&m| m != seen);
+            if let Some(modified) = modified {
+                // A file saved halfway fails to read; it is tried again at the
+                // next file
+                match reload_mapping(reloaded.as_ref().unwrap_or(job)) {
+                    Ok(current) => {
+                        info!(
+                            "Reloaded {} ({} mappings)",
+                            job.config.excel_path.display(),
+                            current.mappings.len()
+                        );
+                        reloaded = Some(current);
+                        mapping_modified = Some(modified);
+                        summary.mapping_reloads += 1;
+                    }
+                    Err(e) => warn!("Keeping the previous mapping: {}", e),
+                }
+            }
+        }
+        let current = reloaded.as_ref().unwrap_or(job);
+
+        let started = Instant::now();
+        let result = qpdf::process_pdf_with_qpdf(pdf_path, current, next_bates);
+        let elapsed = started.elapsed();
+
+        // Per-file outcomes are reported to the caller through `on_file`, so
+        // they are only logged at info level here
+        let file_result = match result {
+            Ok(qpdf::ProcessOutcome::Inserted(inserted)) => {
+                summary.processed += 1;
+                used_mappings.insert(inserted.key.clone());
+                if let Some((_, last)) = inserted.bates {
+                    next_bates = last + 1;
+                }
+                info!("Processed: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Processed,
+                    key: Some(inserted.key),
+                    pages: inserted.pages,
+                    error: None,
+                    qpdf_warnings: Some(inserted.qpdf_stderr).filter(|s| !s.is_empty()),
+                    backup: inserted.backup,
+                    renamed: inserted.renamed,
+                    bates: inserted.bates,
+                    pdfa_lost: inserted.pdfa_lost,
+                    elapsed,
+                }
+            }
+            Ok(qpdf::ProcessOutcome::AlreadyProcessed { key, pages }) => {
+                summary.already_processed += 1;
+                used_mappings.insert(key.clone());
+                info!("Already processed: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::AlreadyProcessed,
+                    key: Some(key),
+                    pages,
+                    error: None,
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+            Ok(qpdf::ProcessOutcome::Excluded { key }) => {
+                summary.excluded += 1;
+                info!("Excluded: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Excluded,
+                    key: Some(key),
+                    pages: Vec::new(),
+                    error: None,
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+            Ok(qpdf::ProcessOutcome::Signed { key }) => {
+                summary.signed += 1;
+                info!("Signed, skipped: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Signed,
+                    key: Some(key),
+                    pages: Vec::new(),
+                    error: None,
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+            Ok(qpdf::ProcessOutcome::Unmatched) => {
+                summary.skipped += 1;
+                info!("Skipped: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Skipped,
+                    key: None,
+                    pages: Vec::new(),
+                    error: None,
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+            Ok(qpdf::ProcessOutcome::Placeholder) => {
+                summary.placeholders += 1;
+                info!("Online-only, skipped: {}", pdf_path.display());
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Placeholder,
+                    key: None,
+                    pages: Vec::new(),
+                    error: None,
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+            Err(e) => {
+                summary.errors += 1;
+                info!("Error processing {}: {}", pdf_path.display(), e);
+                FileResult {
+                    path: pdf_path.clone(),
+                    status: FileStatus::Error,
+                    // The row the file matched, for the checkpoint and report
+                    key: current.lookup(pdf_path).map(|insertion| insertion.key),
+                    pages: Vec::new(),
+                    error: Some(e.to_string()),
+                    qpdf_warnings: None,
+                    backup: None,
+                    renamed: None,
+                    bates: None,
+                    pdfa_lost: None,
+                    elapsed,
+                }
+            }
+        };
+
+        on_file(&file_result);
+
+        if let Some(checkpoint) = checkpoint.as_mut() {
+            let record = CheckpointRecord {
+                file: checkpoint_key(pdf_path),
+                status: file_result.status,
+                key: file_result.key.clone(),
+                error: file_result.error.clone(),
+                bates: file_result
+                    .bates
+                    .zip(job.config.bates.as_ref())
+                    .map(|((first, last), bates)| bates.range(first, last)),
+                pdfa_lost: file_result.pdfa_lost.clone(),
+            };
+
+            // Without a durable checkpoint a crash could lead to double insertion
+            checkpoint
+                .record(&record)
+                .map_err(|e| format!("Failed to write checkpoint, stopping: {}", e))?;
+        }
+
+        summary.files.push(file_result);
+    }
+
+    // Excel entries that had no matching PDF files; unknown for a partial run
+    if summary.interrupted {
+        info!(
+            "Stopped after {} of {} files",
+            summary.files.len(),
+            job.pdf_files.len()
+        );
+        return Ok(summary);
+    }
+
+    // Only a scan that handled every file counts as the last run, so failed
+    // files still qualify for the next incremental run
+    if job.config.files.is_none() && summary.errors == 0 && summary.placeholders == 0 {
+        for dir in &job.config.dirs {
+            if let Err(e) = last_run::write_last_run(dir, run_started) {
+                warn!(
+                    "Failed to record time of this run in {}: {}",
+                    dir.display(),
+                    e
+                );
+            }
+        }
+    }
+    summary.unmapped_excel_entries = reloaded
+        .as_ref()
+        .unwrap_or(job)
+        .mappings
+        .iter()
+        .filter(|(excel_name, entry)| !entry.excluded && !used_mappings.contains(*excel_name))
+        .map(|(excel_name, _)| excel_name.clone())
+        .collect();
+    summary.unmapped_excel_entries.sort();
+
+    info!(
+        "Summary: {} processed, {} already processed, {} skipped, {} errors",
+        summary.processed, summary.already_processed, summary.skipped, summary.errors
+    );
+
+    Ok(summary)
+}
+
+// The cover PDF of each of `pdf_files` found in its folder, or the nearest
+// folder above it within the scanned directory, under the name of the job's
+// cover. The covers found are no documents to process, so they are taken out
+// of `pdf_files`.
+fn find_folder_covers(
+    config: &JobConfig,
+    pdf_files: &mut Vec<PathBuf>,
+) -> HashMap<PathBuf, PathBuf> {
+    let Some(cover_name) = config.bia_path.file_name() else {
+        return HashMap::new();
+    };
+    pdf_files.retain(|pdf_path| pdf_path.file_name() != Some(cover_name));
+
+    // Looked up once per folder
+    let mut by_folder: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+    let mut covers = HashMap::new();
+    for pdf_path in pdf_files.iter() {
+        let Some(folder) = pdf_path.parent() else {
+            continue;
+        };
+        let base_dir = config.dirs.iter().find(|dir| folder.starts_with(dir));
+        let cover = by_folder
+            .entry(folder.to_path_buf())
+            .or_insert_with(|| {
+                folder
+                    .ancestors()
+                    // Listed files outside the scanned directories only
+                    // look in their own folder
+                    .take_while(|dir| match base_dir {
+                        Some(base_dir) => dir.starts_with(base_dir),
+                        None => *dir == folder,
+                    })
+                    .map(|dir| dir.join(cover_name))
+                    .find(|cover| cover.is_file())
+            })
+            .clone();
+        if let Some(cover) = cover {
+            debug!("Cover for {}: {}", pdf_path.display(), cover.display());
+            covers.insert(pdf_path.clone(), cover);
+        }
+    }
+    covers
+}
+
+// When the mapping file was last modified, None when it is not a file the
+// job reads (a database, a Google Sheet, a manifest or no mapping at all)
+fn mapping_file_modified(config: &JobConfig) -> Option<SystemTime> {
+    if config.mapping_db.is_some()
+        || config.google_sheet.is_some()
+        || config.manifest.is_some()
+        || config.page_pattern.is_some()
+    {
+        return None;
+    }
+    config.excel_path.metadata().and_then(|m| m.modified()).ok()
+}
+
+// The cover PDFs the entries of `mappings` name, their dated rows included
+fn mapping_covers(mappings: &HashMap<String, MappingEntry>) -> impl Iterator<Item = &PathBuf> {
+    mappings
+        .values()
+        .flat_map(|entry| std::iter::once(entry).chain(entry.dated.iter().map(|(_, e)| e)))
+        .filter_map(|entry| entry.cover.as_ref())
+}
+
+// `job` with its mapping read again, and the page counts of any new covers
+pub fn reload_mapping(job: &Job) -> Result<Job, Box<dyn std::error::Error>> {
+    let (mappings, report) = read_mapping(&job.config)?;
+    let mut cover_page_counts = job.cover_page_counts.clone();
+    for cover in mapping_covers(&mappings) {
+        count_cover_pages(cover, &mut cover_page_counts)?;
+    }
+    Ok(Job {
+        mappings,
+        mapping_report: Some(report),
+        cover_page_counts,
+        ..job.clone()
+    })
+}
+
+// Prepare and execute a job in one call, without any interaction
+pub fn run(config: JobConfig) -> Result<JobSummary, Box<dyn std::error::Error>> {
+    let job = prepare(config)?;
+    execute(&job, |_| {})
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn template_job_runs_without_bia_pdf() {
+        let dir = fixtures::scratch_dir("template-job");
+        let files = dir.join("files");
+        fs::create_dir_all(&files).unwrap();
+        let target = files.join("HD001.pdf");
+        fixtures::pdf(2).save(&target).unwrap();
+        let template = dir.join("cover.txt");
+        fs::write(&template, "text 100 700 {key}\n").unwrap();
+
+        // No bia.pdf: the template is the cover
+        let mut config = JobConfig::new(vec![files], dir.join("mapping.xlsx"), dir.join("bia.pdf"));
+        config.cover_template = Some(template);
+        let insertion = fixtures::insertion("HD001", vec![1], Position::Front);
+        let job = fixtures::job(config, HashMap::from([(target, insertion)]));
+
+        let summary = execute(&job, |_| {}).unwrap();
+        assert_eq!(summary.files.len(), 1);
+        assert_eq!(summary.processed, 1, "{:?}", summary.files[0].error);
+    }
+
+    #[test]
+    fn failed_file_keeps_its_key() {
+        let dir = fixtures::scratch_dir("failed-key");
+        let files = dir.join("files");
+        fs::create_dir_all(&files).unwrap();
+        let target = files.join("HD001.pdf");
+        fs::write(&target, "not a PDF").unwrap();
+        let bia_path = dir.join("bia.pdf");
+        fixtures::pdf(1).save(&bia_path).unwrap();
+        let checkpoint = dir.join("checkpoint.jsonl");
+
+        let mut config = JobConfig::new(vec![files], dir.join("mapping.xlsx"), &bia_path);
+        config.checkpoint = Some(checkpoint.clone());
+        let insertion = fixtures::insertion("HD001", vec![1], Position::Front);
+        let job = fixtures::job(config, HashMap::from([(target, insertion)]));
+
+        let summary = execute(&job, |_| {}).unwrap();
+        assert_eq!(summary.errors, 1);
+        assert_eq!(summary.files[0].status, FileStatus::Error);
+        assert_eq!(summary.files[0].key.as_deref(), Some("HD001"));
+        let records = load_checkpoint(&checkpoint).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].key.as_deref(), Some("HD001"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rescan_of_an_empty_folder() {
+        let dir = fixtures::scratch_dir("empty-rescan");
+        let files = dir.join("files");
+        fs::create_dir_all(&files).unwrap();
+        let config = JobConfig::new(vec![files.clone()], dir.join("mapping.xlsx"), "bia.pdf");
+        let mut job = fixtures::job(config, HashMap::new());
+        job.manifest = None;
+
+        assert!(rescan(&mut job).is_err());
+        job.config.allow_empty = true;
+        rescan(&mut job).unwrap();
+        assert!(job.pdf_files.is_empty());
+
+        // Files arriving later are found by the next scan
+        fs::create_dir_all(files.join("new")).unwrap();
+        let arrived = files.join("new").join("HD001.pdf");
+        fixtures::pdf(1).save(&arrived).unwrap();
+        rescan(&mut job).unwrap();
+        assert_eq!(job.pdf_files, [arrived]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
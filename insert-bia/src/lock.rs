@@ -0,0 +1,124 @@
+use crate::cleanup::Leftover;
+use log::info;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOCK_FILE_NAME: &str = ".insert-bia.lock";
+const WAIT_INTERVAL: Duration = Duration::from_secs(2);
+
+// Exclusive hold on a directory for one run, so two instances never overwrite
+// the same files at once. The lock file is removed when this is dropped.
+#[derive(Debug)]
+pub struct DirLock {
+    path: PathBuf,
+    _leftover: Leftover,
+}
+
+impl DirLock {
+    // Create the lock file in `dir`. If another instance holds it, fail with
+    // its owner's details, or keep retrying when `wait` is set.
+    pub fn acquire(dir: &Path, wait: bool) -> Result<DirLock, Box<dyn std::error::Error>> {
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut announced = false;
+
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    let leftover = Leftover::file(&path);
+                    if let Err(e) = write_owner(file) {
+                        let _ = fs::remove_file(&path);
+                        return Err(
+                            format!("Failed to write lock {}: {}", path.display(), e).into()
+                        );
+                    }
+                    return Ok(DirLock {
+                        path,
+                        _leftover: leftover,
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    let owner = fs::read_to_string(&path).unwrap_or_default();
+                    let owner = owner.trim();
+
+                    if !wait {
+                        return Err(format!(
+                            "{} is locked by another run ({}); wait for it to finish, or delete {} if that run is no longer active",
+                            dir.display(),
+                            if owner.is_empty() { "unknown owner" } else { owner },
+                            path.display()
+                        )
+                        .into());
+                    }
+
+                    if !announced {
+                        info!("Waiting for lock {} held by {}", path.display(), owner);
+                        announced = true;
+                    }
+                    thread::sleep(WAIT_INTERVAL);
+                }
+                Err(e) => {
+                    return Err(format!("Failed to create lock {}: {}", path.display(), e).into())
+                }
+            }
+        }
+    }
+}
+
+// Lock every directory of a run; on failure the locks already taken are
+// released again
+pub fn acquire_all(
+    dirs: &[PathBuf],
+    wait: bool,
+) -> Result<Vec<DirLock>, Box<dyn std::error::Error>> {
+    dirs.iter().map(|dir| DirLock::acquire(dir, wait)).collect()
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Record who holds the lock, to help whoever runs into it
+fn write_owner(mut file: File) -> io::Result<()> {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let host = std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .unwrap_or_else(|_| "unknown host".to_string());
+
+    writeln!(
+        file,
+        "pid {} on {}, started at {}",
+        std::process::id(),
+        host,
+        started
+    )?;
+    file.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn second_lock_fails_until_the_first_is_dropped() {
+        let dir = fixtures::scratch_dir("lock");
+        let lock = DirLock::acquire(&dir, false).unwrap();
+        let owner = fs::read_to_string(dir.join(LOCK_FILE_NAME)).unwrap();
+        assert!(owner.starts_with(&format!("pid {} ", std::process::id())));
+
+        let error = DirLock::acquire(&dir, false).unwrap_err().to_string();
+        assert!(error.contains("locked by another run"));
+        drop(lock);
+        assert!(!dir.join(LOCK_FILE_NAME).exists());
+        drop(DirLock::acquire(&dir, false).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+// Manifest of exact insertions, for when another system already knows which
+// page goes into which file: no scanning and no filename matching.
+
+use crate::mapping::deserialize_pages;
+use crate::pages::parse_pages;
+use crate::position::{self, Position};
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ManifestEntry {
+    pub pdf_path: PathBuf,
+    // 1-based bia.pdf pages: a number, or a list such as "3-5" or "2,7,9"
+    #[serde(rename = "bia_page", deserialize_with = "deserialize_pages")]
+    pub bia_pages: Vec<u32>,
+    #[serde(default, deserialize_with = "position::deserialize_or_default")]
+    pub position: Position,
+}
+
+// Read a .csv (`pdf_path,bia_page[,position]`, optional header row) or .json
+// (array of objects with those fields) manifest. The page may be a range
+// ("3-5") or a list ("2,7,9", quoted in CSV). Relative paths are resolved
+// against the manifest's directory.
+pub fn read_manifest(path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let is_json = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+
+    let mut entries = if is_json {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str::<Vec<ManifestEntry>>(&content)?
+    } else {
+        read_csv_manifest(path)?
+    };
+
+    let manifest_dir = path.parent().unwrap_or(Path::new("."));
+    for entry in &mut entries {
+        if entry.pdf_path.is_relative() {
+            entry.pdf_path = manifest_dir.join(&entry.pdf_path);
+        }
+    }
+
+    Ok(entries)
+}
+
+fn read_csv_manifest(path: &Path) -> Result<Vec<ManifestEntry>, Box<dyn std::error::Error>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+
+    let mut entries = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let line = index + 1;
+
+        let pdf_path = record.get(0).unwrap_or("");
+        let page = record.get(1).unwrap_or("");
+        if pdf_path.is_empty() {
+            continue;
+        }
+
+        let bia_pages = match parse_pages(page) {
+            Ok(pages) => pages,
+            // A header row
+            Err(_) if line == 1 => continue,
+            Err(e) => return Err(format!("Line {}: {}", line, e).into()),
+        };
+
+        let position = match record.get(2).filter(|s| !s.is_empty()) {
+            Some(position) => position
+                .parse()
+                .map_err(|e| format!("Line {}: {}", line, e))?,
+            None => Position::default(),
+        };
+
+        entries.push(ManifestEntry {
+            pdf_path: PathBuf::from(pdf_path),
+            bia_pages,
+            position,
+        });
+    }
+
+    Ok(entries)
+}
@@ -0,0 +1,1357 @@
+use crate::metadata::DocumentInfo;
+use crate::pages::{format_pages, parse_pages};
+use crate::position::{self, Position};
+use calamine::{open_workbook_auto_from_rs, Data, Ods, Reader, Sheets, Xls, Xlsb, Xlsx};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Cursor};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+// What a mapping file says about one filename
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappingEntry {
+    // 1-based pages of the cover PDF, in the order they are inserted
+    pub pages: Vec<u32>,
+    pub position: Position,
+    // Cover PDF to take the page from instead of the job's
+    pub cover: Option<PathBuf>,
+    // New file name for the processed file, in the same directory
+    pub output_name: Option<String>,
+    // Marked in the skip column: matching files are left alone
+    pub excluded: bool,
+    // 1-based pages of the matching files to delete, such as old separator
+    // sheets; an entry may remove pages without inserting any
+    pub remove_pages: Vec<u32>,
+    // Title, author, subject and keywords to set on the processed file
+    pub info: DocumentInfo,
+    // Password to encrypt the processed file with
+    pub encrypt_password: Option<String>,
+    // Password that opens the matching files, when they are encrypted
+    pub password: Option<String>,
+    // The row's cells by header and by column letter, for cover templates
+    pub fields: HashMap<String, String>,
+    // Rows for the same filename that apply only to files modified within
+    // their dates, replacing this entry. Without an undated row this entry
+    // has no pages and files outside every window are not matched.
+    pub dated: Vec<(DateWindow, MappingEntry)>,
+}
+
+impl MappingEntry {
+    pub fn new(pages: Vec<u32>) -> Self {
+        MappingEntry {
+            pages,
+            position: Position::default(),
+            cover: None,
+            output_name: None,
+            excluded: false,
+            remove_pages: Vec::new(),
+            info: DocumentInfo::default(),
+            encrypt_password: None,
+            password: None,
+            fields: HashMap::new(),
+            dated: Vec::new(),
+        }
+    }
+
+    // The entry for a file last modified at `modified`: the dated row whose
+    // window contains it, else the undated one. None when there is neither.
+    pub fn on_date(&self, modified: Option<SystemTime>) -> Option<MappingEntry> {
+        if let Some(modified) = modified {
+            if let Some((_, entry)) = self.dated.iter().find(|(w, _)| w.contains(modified)) {
+                return Some(entry.clone());
+            }
+        }
+        if self.pages.is_empty()
+            && self.remove_pages.is_empty()
+            && self.fields.is_empty()
+            && !self.excluded
+        {
+            return None;
+        }
+        Some(MappingEntry {
+            dated: Vec::new(),
+            ..self.clone()
+        })
+    }
+}
+
+// When a dated row applies: from the start of its first day to the end of its
+// last, either end open when not given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DateWindow {
+    pub from: Option<SystemTime>,
+    // Exclusive: midnight after the last day
+    pub until: Option<SystemTime>,
+}
+
+impl DateWindow {
+    pub fn contains(&self, time: SystemTime) -> bool {
+        self.from.is_none_or(|from| time >= from) && self.until.is_none_or(|until| time < until)
+    }
+
+    fn overlaps(&self, other: &DateWindow) -> bool {
+        let starts_before_end = |a: &DateWindow, b: &DateWindow| match (a.from, b.until) {
+            (Some(from), Some(until)) => from < until,
+            _ => true,
+        };
+        starts_before_end(self, other) && starts_before_end(other, self)
+    }
+}
+
+// Layout of the mapping file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MappingFormat {
+    // Spreadsheet: .xlsx, .xlsm, .xls, .xlsb, .ods
+    Workbook,
+    // Delimited text exported from another system (comma, semicolon or tab)
+    Csv,
+    // Lists of entries with per-entry options, see `StructuredEntry`
+    Json,
+    Yaml,
+}
+
+impl MappingFormat {
+    // Guess the format from the file extension; anything unknown is read as
+    // a workbook
+    pub fn from_path(path: &Path) -> MappingFormat {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_lowercase);
+        match extension.as_deref() {
+            Some("csv") | Some("tsv") | Some("txt") => MappingFormat::Csv,
+            Some("json") => MappingFormat::Json,
+            Some("yaml") | Some("yml") => MappingFormat::Yaml,
+            _ => MappingFormat::Workbook,
+        }
+    }
+}
+
+// Which worksheet of a workbook holds the mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SheetSelector {
+    Name(String),
+    // 1-based position of the tab
+    Index(usize),
+}
+
+// Which row wins when a filename is in several rows that disagree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicateRowPolicy {
+    First,
+    #[default]
+    Last,
+    // Fail listing the disagreeing rows
+    Error,
+}
+
+// Worksheets whose rows are merged into one mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SheetSet {
+    All,
+    Named(Vec<String>),
+}
+
+// How to read a spreadsheet or CSV mapping; ignored for the other formats
+#[derive(Debug, Clone, Default)]
+pub struct SheetOptions {
+    // The first worksheet when not set
+    pub sheet: Option<SheetSelector>,
+    // Merge these worksheets instead of reading one; overrides `sheet`
+    pub merge_sheets: Option<SheetSet>,
+    // Columns by letter ("C") or header text ("Tên file"); A and B when not set
+    pub filename_column: Option<String>,
+    pub page_column: Option<String>,
+    // Optional column naming the cover PDF of the row
+    pub cover_column: Option<String>,
+    // Optional column with the row's document type, which picks its cover
+    // PDF from `type_covers` (by type, compared case-insensitively) when the
+    // cover column names none
+    pub type_column: Option<String>,
+    pub type_covers: HashMap<String, PathBuf>,
+    // Optional column with the row's position: front, back, replace, overlay,
+    // underlay or after:N
+    pub position_column: Option<String>,
+    // Optional column with the name to rename the processed file to
+    pub output_column: Option<String>,
+    // Optional column where "x" or "skip" excludes the row's files
+    pub skip_column: Option<String>,
+    // Optional column with pages to delete from the row's files
+    pub remove_column: Option<String>,
+    // Optional columns with the title, author, subject and keywords to set in
+    // the document info of the row's files
+    pub title_column: Option<String>,
+    pub author_column: Option<String>,
+    pub subject_column: Option<String>,
+    pub keywords_column: Option<String>,
+    // Optional column with the password to encrypt the row's files with
+    pub encrypt_password_column: Option<String>,
+    // Optional column with the password that opens the row's files
+    pub password_column: Option<String>,
+    // Optional columns with the first and last day (by modification date) of
+    // the files a row applies to
+    pub valid_from_column: Option<String>,
+    pub valid_to_column: Option<String>,
+    // Keep every cell of the rows for a cover template; rows then need no page
+    pub keep_fields: bool,
+    // Rows at the top of the sheet to ignore, such as a title above the table
+    pub skip_rows: usize,
+    // Position of the rows (and structured entries) that do not give one
+    pub default_position: Position,
+    pub duplicate_rows: DuplicateRowPolicy,
+}
+
+// How the rows of a mapping file were interpreted
+#[derive(Debug, Clone, Default)]
+pub struct MappingReport {
+    pub skipped_rows: usize,
+    // 1-based sheet row taken as the header
+    pub header_row: Option<usize>,
+    pub mapped_rows: usize,
+    // Rows marked in the skip column
+    pub excluded_rows: usize,
+    // 1-based sheet rows with content but no filename or page number
+    pub ignored_rows: Vec<usize>,
+    // Filenames in several rows that disagree, whichever row was used
+    pub conflicts: Vec<RowConflict>,
+    // 1-based sheet rows each filename was read from, in order
+    pub rows: HashMap<String, Vec<usize>>,
+    // Report of each merged worksheet; everything above is their totals,
+    // the rows of the sheets one after the other
+    pub sheets: Vec<(String, MappingReport)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RowConflict {
+    pub filename: String,
+    // 1-based sheet rows, in order
+    pub rows: Vec<usize>,
+}
+
+// Read the filename → entry mappings, in `format` or the one implied by the
+// extension. Relative covers named in a spreadsheet column are taken from
+// `cover_dir`; `password` opens an encrypted workbook.
+pub fn read_mappings(
+    path: &Path,
+    format: Option<MappingFormat>,
+    options: &SheetOptions,
+    cover_dir: &Path,
+    password: Option<&str>,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    match format.unwrap_or_else(|| MappingFormat::from_path(path)) {
+        MappingFormat::Workbook => read_excel_mappings(path, options, cover_dir, password),
+        MappingFormat::Csv => read_csv_mappings(path, options, cover_dir),
+        MappingFormat::Json | MappingFormat::Yaml => {
+            read_structured_mappings(path, options.default_position)
+        }
+    }
+}
+
+pub fn read_excel_mappings(
+    excel_path: &Path,
+    options: &SheetOptions,
+    cover_dir: &Path,
+    password: Option<&str>,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let mut workbook = open_any_workbook(excel_path, password)?;
+    let sheet_names = workbook.sheet_names();
+
+    read_sheets(&sheet_names, options, cover_dir, |name| {
+        let range = workbook.worksheet_range(name)?;
+        let rows: Vec<Vec<Data>> = range.rows().map(<[Data]>::to_vec).collect();
+        // The range starts at the first used cell, not necessarily A1
+        let origin = range
+            .start()
+            .map(|(row, column)| (row as usize, column as usize))
+            .unwrap_or_default();
+        Ok((rows, origin))
+    })
+}
+
+// Rows of one worksheet and the 0-based sheet row and column of their first cell
+pub(crate) type SheetRows = (Vec<Vec<Data>>, (usize, usize));
+
+// The mapping in the worksheet(s) of `sheet_names` that `options` selects,
+// each loaded by `read_sheet`
+pub(crate) fn read_sheets(
+    sheet_names: &[String],
+    options: &SheetOptions,
+    cover_dir: &Path,
+    mut read_sheet: impl FnMut(&str) -> Result<SheetRows, Box<dyn std::error::Error>>,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    if let Some(set) = &options.merge_sheets {
+        let names = match set {
+            SheetSet::All => sheet_names.to_vec(),
+            SheetSet::Named(names) => names
+                .iter()
+                .map(|name| find_sheet(sheet_names, &SheetSelector::Name(name.clone())))
+                .collect::<Result<_, _>>()?,
+        };
+        return merge_sheets(&names, options, cover_dir, read_sheet);
+    }
+
+    let name = match &options.sheet {
+        None => match sheet_names.first() {
+            Some(name) => name.clone(),
+            None => return Ok((HashMap::new(), MappingReport::default())),
+        },
+        Some(selector) => find_sheet(sheet_names, selector)?,
+    };
+    let (rows, origin) = read_sheet(&name)?;
+    read_rows(&rows, origin, options, cover_dir)
+}
+
+// The rows of several worksheets, read with the same columns, as one mapping.
+// A filename may appear in more than one sheet if the rows agree; rows that
+// disagree are listed in the error. Empty sheets are passed over.
+fn merge_sheets(
+    names: &[String],
+    options: &SheetOptions,
+    cover_dir: &Path,
+    mut read_sheet: impl FnMut(&str) -> Result<SheetRows, Box<dyn std::error::Error>>,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let mut merged: HashMap<String, MappingEntry> = HashMap::new();
+    // Sheet each merged entry came from
+    let mut sources: HashMap<String, &str> = HashMap::new();
+    let mut report = MappingReport::default();
+    let mut conflicts = Vec::new();
+
+    for name in names {
+        let (rows, origin) = read_sheet(name)?;
+        if rows.is_empty() {
+            continue;
+        }
+        let (mappings, sheet_report) = read_rows(&rows, origin, options, cover_dir)
+            .map_err(|e| format!("Sheet '{}': {}", name, e))?;
+
+        for (filename, entry) in mappings {
+            match merged.get(&filename) {
+                Some(existing) if *existing != entry => conflicts.push(format!(
+                    "{}: {} in sheet '{}', {} in sheet '{}'",
+                    filename,
+                    describe_entry(existing),
+                    sources[&filename],
+                    describe_entry(&entry),
+                    name
+                )),
+                Some(_) => {}
+                None => {
+                    sources.insert(filename.clone(), name);
+                    merged.insert(filename, entry);
+                }
+            }
+        }
+
+        report.skipped_rows += sheet_report.skipped_rows;
+        report.mapped_rows += sheet_report.mapped_rows;
+        report.excluded_rows += sheet_report.excluded_rows;
+        report.ignored_rows.extend(&sheet_report.ignored_rows);
+        report
+            .conflicts
+            .extend(sheet_report.conflicts.iter().cloned());
+        for (filename, rows) in &sheet_report.rows {
+            report
+                .rows
+                .entry(filename.clone())
+                .or_default()
+                .extend(rows);
+        }
+        report.sheets.push((name.clone(), sheet_report));
+    }
+
+    if !conflicts.is_empty() {
+        conflicts.sort();
+        return Err(format!(
+            "{} filename(s) map differently in different sheets:\n  {}",
+            conflicts.len(),
+            conflicts.join("\n  ")
+        )
+        .into());
+    }
+
+    Ok((merged, report))
+}
+
+// "page 3", "pages 2-4, back, rename to X.pdf" or "excluded", for messages
+fn describe_entry(entry: &MappingEntry) -> String {
+    if entry.excluded {
+        return "excluded".to_string();
+    }
+
+    let mut parts = match entry.pages.len() {
+        0 => Vec::new(),
+        1 => vec![format!("page {}", entry.pages[0])],
+        _ => vec![format!("pages {}", format_pages(&entry.pages))],
+    };
+    if !entry.remove_pages.is_empty() {
+        parts.push(format!("remove {}", format_pages(&entry.remove_pages)));
+    }
+    if entry.position != Position::Front {
+        parts.push(entry.position.to_string());
+    }
+    if let Some(cover) = &entry.cover {
+        parts.push(format!("from {}", cover.display()));
+    }
+    if let Some(output_name) = &entry.output_name {
+        parts.push(format!("rename to {}", output_name));
+    }
+    parts.join(", ")
+}
+
+// The mappings in the rows of a sheet, whose first cell is at 0-based sheet
+// row and column `origin`. After the skipped rows, the first row with content
+// is the header when its page cell is not a number. Other rows without a
+// filename or a page number are ignored.
+pub(crate) fn read_rows(
+    rows: &[Vec<Data>],
+    origin: (usize, usize),
+    options: &SheetOptions,
+    cover_dir: &Path,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let (first_row, first_column) = origin;
+    let sheet_row = |index: usize| first_row + index + 1;
+    let has_content = |row: &Vec<Data>| row.iter().any(|cell| cell_text(cell).is_some());
+
+    let mut report = MappingReport::default();
+    let mut rows: Vec<(usize, &Vec<Data>)> = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| (sheet_row(index), row))
+        .collect();
+    report.skipped_rows = (first_row + 1..=options.skip_rows)
+        .filter(|row| rows.iter().any(|(r, cells)| r == row && has_content(cells)))
+        .count();
+    rows.retain(|(row, cells)| *row > options.skip_rows && has_content(cells));
+
+    // Header names are looked up in the first row with content
+    let header: Vec<String> = rows
+        .first()
+        .map(|(_, row)| {
+            row.iter()
+                .map(|cell| cell_text(cell).unwrap_or_default())
+                .collect()
+        })
+        .unwrap_or_default();
+    let filename_column =
+        resolve_column(options.filename_column.as_deref(), 0, &header, first_column)?;
+    let page_column = resolve_column(options.page_column.as_deref(), 1, &header, first_column)?;
+    // Optional columns are only read when given
+    let optional = |spec: &Option<String>| match spec {
+        Some(spec) => resolve_column(Some(spec), 0, &header, first_column),
+        None => Ok(None),
+    };
+    let cover_column = optional(&options.cover_column)?;
+    let type_column = optional(&options.type_column)?;
+    let position_column = optional(&options.position_column)?;
+    let output_column = optional(&options.output_column)?;
+    let skip_column = optional(&options.skip_column)?;
+    let remove_column = optional(&options.remove_column)?;
+    let title_column = optional(&options.title_column)?;
+    let author_column = optional(&options.author_column)?;
+    let subject_column = optional(&options.subject_column)?;
+    let keywords_column = optional(&options.keywords_column)?;
+    let encrypt_password_column = optional(&options.encrypt_password_column)?;
+    let password_column = optional(&options.password_column)?;
+    let valid_from_column = optional(&options.valid_from_column)?;
+    let valid_to_column = optional(&options.valid_to_column)?;
+
+    // Most likely a misspelt header that happens to read as a column letter
+    let width = rows.iter().map(|(_, row)| row.len()).max().unwrap_or(0);
+    for (spec, column) in [
+        (&options.filename_column, filename_column),
+        (&options.page_column, page_column),
+        (&options.cover_column, cover_column),
+        (&options.type_column, type_column),
+        (&options.position_column, position_column),
+        (&options.output_column, output_column),
+        (&options.skip_column, skip_column),
+        (&options.remove_column, remove_column),
+        (&options.title_column, title_column),
+        (&options.author_column, author_column),
+        (&options.subject_column, subject_column),
+        (&options.keywords_column, keywords_column),
+        (&options.encrypt_password_column, encrypt_password_column),
+        (&options.password_column, password_column),
+        (&options.valid_from_column, valid_from_column),
+        (&options.valid_to_column, valid_to_column),
+    ] {
+        if let (Some(spec), true) = (spec, width > 0 && column.is_none_or(|c| c >= width)) {
+            return Err(format!(
+                "No column '{}' (the sheet has {} columns; header row: {})",
+                spec,
+                width,
+                header.join(", ")
+            )
+            .into());
+        }
+    }
+    let (Some(filename_column), Some(page_column)) = (filename_column, page_column) else {
+        // Only reached for an empty sheet with the default columns
+        return Ok((HashMap::new(), report));
+    };
+
+    let remove_pages = |cells: &Vec<Data>| {
+        remove_column
+            .and_then(|c| cells.get(c))
+            .and_then(cell_pages)
+    };
+    if let Some((row, cells)) = rows.first() {
+        if cells.get(page_column).and_then(cell_pages).is_none() && remove_pages(cells).is_none() {
+            report.header_row = Some(*row);
+            rows.remove(0);
+        }
+    }
+    let header_names = report.header_row.map(|_| header.clone());
+
+    // Every row read for each filename, resolved once all are known
+    let mut rows_by_file: HashMap<String, Vec<(usize, MappingEntry)>> = HashMap::new();
+    let mut dated_by_file: HashMap<String, Vec<(usize, DateWindow, MappingEntry)>> = HashMap::new();
+    for (row, cells) in rows {
+        let filename = cells.get(filename_column).and_then(cell_text);
+        let pages = cells.get(page_column).and_then(cell_pages);
+        let remove = remove_pages(cells);
+        let excluded = skip_column
+            .and_then(|c| cells.get(c))
+            .and_then(cell_text)
+            .is_some_and(|mark| is_skip_mark(&mark));
+        let entry = match (&filename, pages) {
+            // Excluded rows need no page
+            (Some(_), pages) if excluded => {
+                report.excluded_rows += 1;
+                MappingEntry {
+                    excluded: true,
+                    ..MappingEntry::new(pages.unwrap_or_default())
+                }
+            }
+            // Rows that only remove pages need no page either, nor do rows
+            // filling in a cover template
+            (Some(_), pages) if pages.is_some() || remove.is_some() || options.keep_fields => {
+                let position = match position_column
+                    .and_then(|c| cells.get(c))
+                    .and_then(cell_text)
+                {
+                    Some(position) => position
+                        .parse()
+                        .map_err(|e| format!("Row {}: {}", row, e))?,
+                    None => options.default_position,
+                };
+                let output_name = match output_column.and_then(|c| cells.get(c)).and_then(cell_text)
+                {
+                    Some(name) => {
+                        Some(output_file_name(&name).map_err(|e| format!("Row {}: {}", row, e))?)
+                    }
+                    None => None,
+                };
+                let cover = match cover_column.and_then(|c| cells.get(c)).and_then(cell_text) {
+                    Some(cover) => Some(cover_dir.join(cover)),
+                    None => match type_column.and_then(|c| cells.get(c)).and_then(cell_text) {
+                        Some(kind) => Some(
+                            type_cover(&options.type_covers, &kind)
+                                .map_err(|e| format!("Row {}: {}", row, e))?,
+                        ),
+                        None => None,
+                    },
+                };
+                let text =
+                    |column: Option<usize>| column.and_then(|c| cells.get(c)).and_then(cell_text);
+                report.mapped_rows += 1;
+                MappingEntry {
+                    position,
+                    cover,
+                    output_name,
+                    remove_pages: remove.unwrap_or_default(),
+                    info: DocumentInfo {
+                        title: text(title_column),
+                        author: text(author_column),
+                        subject: text(subject_column),
+                        keywords: text(keywords_column),
+                    },
+                    encrypt_password: text(encrypt_password_column),
+                    password: text(password_column),
+                    fields: if options.keep_fields {
+                        row_fields(cells, header_names.as_deref(), first_column)
+                    } else {
+                        HashMap::new()
+                    },
+                    ..MappingEntry::new(pages.unwrap_or_default())
+                }
+            }
+            _ => {
+                report.ignored_rows.push(row);
+                continue;
+            }
+        };
+        let date = |column: Option<usize>| match column.and_then(|c| cells.get(c)) {
+            Some(cell) if cell_text(cell).is_some() || matches!(cell, Data::DateTime(_)) => {
+                cell_date(cell)
+                    .map(Some)
+                    .ok_or_else(|| format!("Row {}: invalid date (use YYYY-MM-DD)", row))
+            }
+            _ => Ok(None),
+        };
+        let window = DateWindow {
+            from: date(valid_from_column)?,
+            // Up to the end of the last day
+            until: date(valid_to_column)?.map(|to| to + Duration::from_secs(SECONDS_PER_DAY)),
+        };
+        let Some(filename) = filename else {
+            continue;
+        };
+        if window.from.is_some() || window.until.is_some() {
+            dated_by_file
+                .entry(mapping_key(&filename))
+                .or_default()
+                .push((row, window, entry));
+        } else {
+            rows_by_file
+                .entry(mapping_key(&filename))
+                .or_default()
+                .push((row, entry));
+        }
+    }
+
+    let mut mappings = HashMap::new();
+    let mut errors = Vec::new();
+    for (filename, mut entries) in rows_by_file {
+        let rows: Vec<usize> = entries.iter().map(|(row, _)| *row).collect();
+        report.rows.insert(filename.clone(), rows.clone());
+        let (_, first) = &entries[0];
+        if entries.iter().any(|(_, entry)| entry != first) {
+            if options.duplicate_rows == DuplicateRowPolicy::Error {
+                let described: Vec<String> = entries
+                    .iter()
+                    .map(|(row, entry)| format!("row {}: {}", row, describe_entry(entry)))
+                    .collect();
+                errors.push(format!("{} ({})", filename, described.join("; ")));
+            }
+            report.conflicts.push(RowConflict {
+                filename: filename.clone(),
+                rows,
+            });
+        }
+
+        let (_, entry) = match options.duplicate_rows {
+            DuplicateRowPolicy::First => entries.swap_remove(0),
+            _ => entries.pop().expect("at least one row per filename"),
+        };
+        mappings.insert(filename, entry);
+    }
+
+    // Dated rows go with the undated entry, or an empty one without it
+    for (filename, mut dated) in dated_by_file {
+        dated.sort_by_key(|(_, window, _)| window.from);
+        for (index, (row, window, _)) in dated.iter().enumerate() {
+            if let Some((other_row, _, _)) = dated[index + 1..]
+                .iter()
+                .find(|(_, other, _)| window.overlaps(other))
+            {
+                errors.push(format!(
+                    "{} (rows {} and {} have overlapping dates)",
+                    filename, row, other_row
+                ));
+            }
+        }
+
+        let rows = report.rows.entry(filename.clone()).or_default();
+        rows.extend(dated.iter().map(|(row, _, _)| *row));
+        rows.sort();
+        let entry = mappings
+            .entry(filename)
+            .or_insert_with(|| MappingEntry::new(Vec::new()));
+        entry.dated = dated
+            .into_iter()
+            .map(|(_, window, dated_entry)| (window, dated_entry))
+            .collect();
+    }
+    report.conflicts.sort_by_key(|conflict| conflict.rows[0]);
+
+    if !errors.is_empty() {
+        errors.sort();
+        return Err(format!(
+            "{} filename(s) have rows that disagree:\n  {}",
+            errors.len(),
+            errors.join("\n  ")
+        )
+        .into());
+    }
+
+    Ok((mappings, report))
+}
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+// Days from the spreadsheet epoch (1899-12-30) to 1970-01-01
+const SERIAL_DATE_UNIX_EPOCH: f64 = 25569.0;
+
+// A date cell, or text such as "2024-07-01", as midnight UTC of that day
+fn cell_date(cell: &Data) -> Option<SystemTime> {
+    let serial = match cell {
+        Data::DateTime(date) => date.as_f64(),
+        Data::Float(f) => *f,
+        Data::Int(i) => *i as f64,
+        Data::String(s) | Data::DateTimeIso(s) => {
+            let day = s.trim().get(..10)?;
+            return humantime::parse_rfc3339_weak(&format!("{} 00:00:00", day)).ok();
+        }
+        _ => return None,
+    };
+    let days = (serial - SERIAL_DATE_UNIX_EPOCH).floor();
+    if days < 0.0 {
+        return None;
+    }
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(days as u64 * SECONDS_PER_DAY))
+}
+
+// The cover PDF configured for the document type `kind`
+fn type_cover(type_covers: &HashMap<String, PathBuf>, kind: &str) -> Result<PathBuf, String> {
+    let wanted = kind.trim().to_lowercase();
+    if let Some((_, cover)) = type_covers
+        .iter()
+        .find(|(known, _)| known.trim().to_lowercase() == wanted)
+    {
+        return Ok(cover.clone());
+    }
+
+    let mut known: Vec<&str> = type_covers.keys().map(String::as_str).collect();
+    known.sort();
+    Err(format!(
+        "no cover PDF for type '{}' (covers are configured for: {})",
+        kind.trim(),
+        if known.is_empty() {
+            "none".to_string()
+        } else {
+            known.join(", ")
+        }
+    ))
+}
+
+fn is_skip_mark(mark: &str) -> bool {
+    matches!(
+        mark.trim().to_lowercase().as_str(),
+        "x" | "skip" | "yes" | "y" | "true" | "1"
+    )
+}
+
+// The non-empty cells of a row by header text, when there is a header, and
+// by column letter
+fn row_fields(
+    cells: &[Data],
+    header: Option<&[String]>,
+    first_column: usize,
+) -> HashMap<String, String> {
+    let mut fields = HashMap::new();
+    for (index, cell) in cells.iter().enumerate() {
+        let Some(text) = cell_text(cell) else {
+            continue;
+        };
+        if let Some(name) = header.and_then(|header| header.get(index)) {
+            if !name.trim().is_empty() {
+                fields.insert(name.trim().to_string(), text.clone());
+            }
+        }
+        fields.insert(column_letters(first_column + index), text);
+    }
+    fields
+}
+
+// 0 → "A", 25 → "Z", 26 → "AA"
+fn column_letters(index: usize) -> String {
+    let mut letters = Vec::new();
+    let mut number = index + 1;
+    while number > 0 {
+        let remainder = (number - 1) % 26;
+        letters.push(b'A' + remainder as u8);
+        number = (number - 1) / 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).expect("ASCII letters")
+}
+
+// A new name for a processed file: a bare file name, given the .pdf
+// extension when it has none
+pub(crate) fn output_file_name(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    if name.contains(['/', '\\']) || name == "." || name == ".." {
+        return Err(format!(
+            "output name '{}' must be a file name, not a path",
+            name
+        ));
+    }
+
+    if Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+    {
+        Ok(name.to_string())
+    } else {
+        Ok(format!("{}.pdf", name))
+    }
+}
+
+// Index within the rows of the column `spec` names: a header text (matched
+// case-insensitively), else a column letter, counted from the sheet's
+// `first_column`. Column `default` (A = 0) when not given. None for a column
+// left of the rows.
+fn resolve_column(
+    spec: Option<&str>,
+    default: usize,
+    header: &[String],
+    first_column: usize,
+) -> Result<Option<usize>, Box<dyn std::error::Error>> {
+    let Some(spec) = spec.map(str::trim) else {
+        return Ok(default.checked_sub(first_column));
+    };
+
+    let wanted = spec.to_lowercase();
+    if let Some(index) = header
+        .iter()
+        .position(|h| h.trim().to_lowercase() == wanted)
+    {
+        return Ok(Some(index));
+    }
+    if let Some(index) = column_letter_index(spec) {
+        return Ok(index.checked_sub(first_column));
+    }
+
+    Err(format!(
+        "No column '{}' (not a column letter, and the header row has: {})",
+        spec,
+        header.join(", ")
+    )
+    .into())
+}
+
+// "A" → 0, "Z" → 25, "AA" → 26
+fn column_letter_index(letters: &str) -> Option<usize> {
+    if letters.is_empty() || letters.len() > 3 || !letters.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return None;
+    }
+
+    let number = letters
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0, |number, b| number * 26 + (b - b'A' + 1) as usize);
+    Some(number - 1)
+}
+
+fn cell_text(cell: &Data) -> Option<String> {
+    let text = match cell {
+        Data::String(s) => s.trim().to_string(),
+        Data::Float(f) => f.to_string(),
+        Data::Int(i) => i.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+// A page number, or a list of pages such as "3-5" or "2,7,9"
+fn cell_pages(cell: &Data) -> Option<Vec<u32>> {
+    let page = match cell {
+        Data::Int(i) => *i as u32,
+        Data::Float(f) => *f as u32,
+        Data::String(s) => {
+            let s = s.trim();
+            // Text exports may write whole numbers as "3.0"
+            match s.parse::<f64>() {
+                Ok(n) if n >= 0.0 => n as u32,
+                _ => return parse_pages(s).ok(),
+            }
+        }
+        _ => return None,
+    };
+    (page > 0).then(|| vec![page])
+}
+
+// Name of the worksheet `selector` picks; names match case-insensitively
+fn find_sheet(
+    sheet_names: &[String],
+    selector: &SheetSelector,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let found = match selector {
+        SheetSelector::Name(name) => {
+            sheet_names.iter().find(|sheet| *sheet == name).or_else(|| {
+                sheet_names
+                    .iter()
+                    .find(|sheet| sheet.trim().to_lowercase() == name.trim().to_lowercase())
+            })
+        }
+        SheetSelector::Index(index) => index.checked_sub(1).and_then(|i| sheet_names.get(i)),
+    };
+
+    found.cloned().ok_or_else(|| {
+        let wanted = match selector {
+            SheetSelector::Name(name) => format!("No worksheet named '{}'", name),
+            SheetSelector::Index(index) => format!("No worksheet number {}", index),
+        };
+        format!("{} (the workbook has: {})", wanted, sheet_names.join(", ")).into()
+    })
+}
+
+const OLE_SIGNATURE: [u8; 8] = [0xD0, 0xCF, 0x11, 0xE0, 0xA1, 0xB1, 0x1A, 0xE1];
+const ZIP_SIGNATURE: [u8; 4] = *b"PK\x03\x04";
+
+// An encrypted .xlsx is an OLE container holding the real workbook in an
+// "EncryptedPackage" stream; its name appears in the directory in UTF-16
+fn is_encrypted_ooxml(data: &[u8]) -> bool {
+    let stream_name: Vec<u8> = "EncryptedPackage"
+        .encode_utf16()
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    data.starts_with(&OLE_SIGNATURE)
+        && data
+            .windows(stream_name.len())
+            .any(|w| w == stream_name.as_slice())
+}
+
+// Whether the workbook at `path` is password-protected, so the caller can ask
+// for the password before reading it
+pub fn is_encrypted_workbook(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|data| is_encrypted_ooxml(&data))
+}
+
+// Open a workbook of any format calamine reads. The file's signature wins
+// over its extension, since exported files are often misnamed (an old-format
+// workbook saved as .xlsx, or the other way round). Encrypted workbooks are
+// decrypted in memory with `password`.
+fn open_any_workbook(
+    path: &Path,
+    password: Option<&str>,
+) -> Result<Sheets<Cursor<Vec<u8>>>, Box<dyn std::error::Error>> {
+    // OpenDocument files start with an uncompressed "mimetype" entry
+    const ODS_MIMETYPE: &[u8] = b"application/vnd.oasis.opendocument.spreadsheet";
+
+    let mut data = fs::read(path)?;
+    if is_encrypted_ooxml(&data) {
+        let Some(password) = password else {
+            return Err("the workbook is password-protected and no password was given".into());
+        };
+        data = office_crypto::decrypt_from_bytes(data, password)
+            .map_err(|e| format!("failed to decrypt the workbook: {}", e))?;
+        // A wrong password decrypts to garbage rather than failing
+        if !data.starts_with(&ZIP_SIGNATURE) {
+            return Err("wrong password for the workbook".into());
+        }
+    }
+
+    let header = &data[..data.len().min(128)];
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+
+    let workbook = if header.starts_with(&OLE_SIGNATURE) {
+        Sheets::Xls(Xls::new(Cursor::new(data))?)
+    } else if header.starts_with(&ZIP_SIGNATURE) {
+        if header
+            .windows(ODS_MIMETYPE.len())
+            .any(|w| w == ODS_MIMETYPE)
+        {
+            Sheets::Ods(Ods::new(Cursor::new(data))?)
+        } else if extension.as_deref() == Some("xlsb") {
+            Sheets::Xlsb(Xlsb::new(Cursor::new(data))?)
+        } else {
+            Sheets::Xlsx(Xlsx::new(Cursor::new(data))?)
+        }
+    } else {
+        open_workbook_auto_from_rs(Cursor::new(data))?
+    };
+    Ok(workbook)
+}
+
+// Same columns as the workbook: filename, page number
+pub fn read_csv_mappings(
+    csv_path: &Path,
+    options: &SheetOptions,
+    cover_dir: &Path,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let delimiter = sniff_delimiter(csv_path)?;
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .delimiter(delimiter)
+        .from_path(csv_path)?;
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        rows.push(
+            record
+                .iter()
+                .map(|field| Data::String(field.to_string()))
+                .collect(),
+        );
+    }
+
+    read_rows(&rows, (0, 0), options, cover_dir)
+}
+
+// One entry of a .json/.yaml mapping file, e.g.
+//   - file: hoadon
+//     page: 1                 # or a list: "3-5", "2,7,9"
+//     position: back          # optional: front (default), back, after:N
+//     cover: covers/other.pdf # optional, relative to the mapping file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StructuredEntry {
+    file: String,
+    // 1-based, as in the spreadsheet
+    #[serde(deserialize_with = "deserialize_pages")]
+    page: Vec<u32>,
+    #[serde(default, deserialize_with = "position::deserialize_optional")]
+    position: Option<Position>,
+    #[serde(default)]
+    cover: Option<PathBuf>,
+}
+
+pub(crate) fn deserialize_pages<'de, D>(deserializer: D) -> Result<Vec<u32>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Pages {
+        Number(u32),
+        List(String),
+    }
+
+    match Pages::deserialize(deserializer)? {
+        Pages::Number(0) => Err(serde::de::Error::custom("page 0 (pages start at 1)")),
+        Pages::Number(page) => Ok(vec![page]),
+        Pages::List(list) => parse_pages(&list).map_err(serde::de::Error::custom),
+    }
+}
+
+// A list of entries in JSON or YAML (YAML also accepts JSON)
+pub fn read_structured_mappings(
+    path: &Path,
+    default_position: Position,
+) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path)?;
+    let entries: Vec<StructuredEntry> = match MappingFormat::from_path(path) {
+        MappingFormat::Json => serde_json::from_str(&content)?,
+        _ => serde_yaml::from_str(&content)?,
+    };
+
+    let mapping_dir = path.parent().unwrap_or(Path::new("."));
+    let mut mappings = HashMap::new();
+    let mut report = MappingReport::default();
+    for entry in entries {
+        if let Some(mapped) = insert_mapping(&mut mappings, entry.file.trim(), entry.page) {
+            mapped.position = entry.position.unwrap_or(default_position);
+            mapped.cover = entry.cover.map(|cover| mapping_dir.join(cover));
+        }
+        report.mapped_rows += 1;
+    }
+
+    Ok((mappings, report))
+}
+
+// ERP exports use ';' or tabs as often as ','; pick whichever the first line
+// has most of (',' on a tie)
+fn sniff_delimiter(csv_path: &Path) -> Result<u8, Box<dyn std::error::Error>> {
+    let mut first_line = String::new();
+    BufReader::new(File::open(csv_path)?).read_line(&mut first_line)?;
+
+    let delimiter = [b'\t', b';', b',']
+        .into_iter()
+        .max_by_key(|&d| first_line.bytes().filter(|&b| b == d).count())
+        .unwrap_or(b',');
+    Ok(delimiter)
+}
+
+// Add the mapping of `filename` to 1-based `pages` with default options,
+// returning it for the caller to set any others
+fn insert_mapping<'a>(
+    mappings: &'a mut HashMap<String, MappingEntry>,
+    filename: &str,
+    pages: Vec<u32>,
+) -> Option<&'a mut MappingEntry> {
+    if filename.is_empty() {
+        return None;
+    }
+
+    let entry = mappings
+        .entry(mapping_key(filename))
+        .insert_entry(MappingEntry::new(pages));
+    Some(entry.into_mut())
+}
+
+// The key a filename is mapped under. A relative path ("customerA/hoa.pdf")
+// is kept, with forward slashes, to be matched against the file's path under
+// its base directory; an absolute one is reduced to its last component.
+fn mapping_key(filename: &str) -> String {
+    let path = filename.replace('\\', "/");
+    let path = path.trim_start_matches("./");
+    let is_absolute = path.starts_with('/') || path.get(1..2) == Some(":");
+    if path.contains('/') && !is_absolute {
+        return path.to_string();
+    }
+
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn text(s: &str) -> Data {
+        Data::String(s.to_string())
+    }
+
+    fn read(
+        rows: Vec<Vec<Data>>,
+        options: &SheetOptions,
+    ) -> Result<(HashMap<String, MappingEntry>, MappingReport), Box<dyn std::error::Error>> {
+        read_rows(&rows, (0, 0), options, Path::new("covers"))
+    }
+
+    #[test]
+    fn header_row_is_found() {
+        let rows = vec![
+            vec![text("Tên file"), text("Trang")],
+            vec![text("a.pdf"), Data::Int(3)],
+            vec![text("b"), text("2-4")],
+            vec![text(""), text("note")],
+        ];
+        let (mappings, report) = read(rows, &SheetOptions::default()).unwrap();
+        assert_eq!(report.header_row, Some(1));
+        assert_eq!(report.mapped_rows, 2);
+        assert_eq!(report.ignored_rows, [4]);
+        assert_eq!(mappings["a.pdf"].pages, [3]);
+        assert_eq!(mappings["b"].pages, [2, 3, 4]);
+
+        // Without one, the first row is mapped too
+        let rows = vec![vec![text("a.pdf"), Data::Float(1.0)]];
+        let (mappings, report) = read(rows, &SheetOptions::default()).unwrap();
+        assert_eq!(report.header_row, None);
+        assert_eq!(mappings["a.pdf"].pages, [1]);
+    }
+
+    #[test]
+    fn columns_by_header_letter_or_default() {
+        let header = [
+            "Ghi chú".to_string(),
+            "Tên file".to_string(),
+            "A".to_string(),
+        ];
+        assert_eq!(
+            resolve_column(Some(" tên FILE "), 0, &header, 0).unwrap(),
+            Some(1)
+        );
+        // A header named like a letter wins over the letter
+        assert_eq!(resolve_column(Some("a"), 0, &header, 0).unwrap(), Some(2));
+        assert_eq!(resolve_column(Some("B"), 0, &[], 0).unwrap(), Some(1));
+        // Counted from the sheet's first used column
+        assert_eq!(resolve_column(Some("C"), 0, &[], 1).unwrap(), Some(1));
+        assert_eq!(resolve_column(Some("A"), 0, &[], 1).unwrap(), None);
+        assert_eq!(resolve_column(None, 1, &[], 1).unwrap(), Some(0));
+        assert!(resolve_column(Some("Số trang"), 0, &header, 0).is_err());
+    }
+
+    #[test]
+    fn columns_given_by_header() {
+        let rows = vec![
+            vec![text("Trang"), text("Tên file")],
+            vec![Data::Int(2), text("a.pdf")],
+        ];
+        let options = SheetOptions {
+            filename_column: Some("tên file".to_string()),
+            page_column: Some("A".to_string()),
+            ..SheetOptions::default()
+        };
+        let (mappings, _) = read(rows.clone(), &options).unwrap();
+        assert_eq!(mappings["a.pdf"].pages, [2]);
+
+        let options = SheetOptions {
+            page_column: Some("Z".to_string()),
+            ..SheetOptions::default()
+        };
+        assert!(read(rows, &options)
+            .unwrap_err()
+            .to_string()
+            .contains("No column 'Z'"));
+    }
+
+    #[test]
+    fn column_letters_both_ways() {
+        for (index, letters) in [(0, "A"), (25, "Z"), (26, "AA"), (701, "ZZ"), (702, "AAA")] {
+            assert_eq!(column_letters(index), letters);
+            assert_eq!(column_letter_index(letters), Some(index));
+        }
+        assert_eq!(column_letter_index("ab"), Some(27));
+        for invalid in ["", "A1", "ABCD", "Ä"] {
+            assert_eq!(column_letter_index(invalid), None);
+        }
+    }
+
+    #[test]
+    fn page_cells() {
+        assert_eq!(cell_pages(&Data::Int(3)), Some(vec![3]));
+        assert_eq!(cell_pages(&Data::Float(3.0)), Some(vec![3]));
+        assert_eq!(cell_pages(&Data::Float(2.9)), Some(vec![2]));
+        assert_eq!(cell_pages(&text(" 3.0 ")), Some(vec![3]));
+        assert_eq!(cell_pages(&text("2,7")), Some(vec![2, 7]));
+        assert_eq!(cell_pages(&Data::Int(0)), None);
+        assert_eq!(cell_pages(&text("0")), None);
+        assert_eq!(cell_pages(&text("Trang")), None);
+        assert_eq!(cell_pages(&Data::Empty), None);
+    }
+
+    #[test]
+    fn csv_delimiter() {
+        let dir = fixtures::scratch_dir("sniff-delimiter");
+        let path = dir.join("mapping.csv");
+        for (first_line, delimiter) in [
+            ("file;page;cover\n", b';'),
+            ("file\tpage\n", b'\t'),
+            ("file,page\n", b','),
+            ("file;page,cover\n", b','),
+            ("file\n", b','),
+        ] {
+            fs::write(&path, format!("{}a.pdf;1\n", first_line)).unwrap();
+            assert_eq!(
+                sniff_delimiter(&path).unwrap(),
+                delimiter,
+                "{:?}",
+                first_line
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dated_rows() {
+        let options = SheetOptions {
+            valid_from_column: Some("C".to_string()),
+            valid_to_column: Some("D".to_string()),
+            ..SheetOptions::default()
+        };
+        let day =
+            |date: &str| humantime::parse_rfc3339_weak(&format!("{} 12:00:00", date)).unwrap();
+        let rows = vec![
+            vec![
+                text("a.pdf"),
+                Data::Int(1),
+                text("2024-01-01"),
+                text("2024-06-30"),
+            ],
+            vec![text("a.pdf"), Data::Int(2), text("2024-07-01"), Data::Empty],
+            vec![text("a.pdf"), Data::Int(3)],
+        ];
+        let (mappings, report) = read(rows, &options).unwrap();
+        let entry = &mappings["a.pdf"];
+        assert_eq!(entry.dated.len(), 2);
+        assert_eq!(report.rows["a.pdf"], [1, 2, 3]);
+        let pages = |date| entry.on_date(Some(day(date))).unwrap().pages;
+        assert_eq!(pages("2024-06-30"), [1]);
+        assert_eq!(pages("2025-01-01"), [2]);
+        assert_eq!(pages("2023-12-31"), [3]);
+
+        let rows = vec![
+            vec![
+                text("a.pdf"),
+                Data::Int(1),
+                text("2024-01-01"),
+                text("2024-06-30"),
+            ],
+            vec![text("a.pdf"), Data::Int(2), text("2024-06-01"), Data::Empty],
+        ];
+        let error = read(rows, &options).unwrap_err().to_string();
+        assert!(
+            error.contains("rows 1 and 2 have overlapping dates"),
+            "{}",
+            error
+        );
+
+        let rows = vec![vec![text("a.pdf"), Data::Int(1), text("soon")]];
+        assert!(read(rows, &options).is_err());
+    }
+
+    #[test]
+    fn duplicate_rows() {
+        let rows = || {
+            vec![
+                vec![text("a.pdf"), Data::Int(1)],
+                vec![text("b.pdf"), Data::Int(5)],
+                vec![text("a.pdf"), Data::Int(2)],
+            ]
+        };
+        let with = |duplicate_rows| SheetOptions {
+            duplicate_rows,
+            ..SheetOptions::default()
+        };
+
+        let (mappings, report) = read(rows(), &with(DuplicateRowPolicy::Last)).unwrap();
+        assert_eq!(mappings["a.pdf"].pages, [2]);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].rows, [1, 3]);
+        let (mappings, _) = read(rows(), &with(DuplicateRowPolicy::First)).unwrap();
+        assert_eq!(mappings["a.pdf"].pages, [1]);
+        let error = read(rows(), &with(DuplicateRowPolicy::Error))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("row 1: page 1; row 3: page 2"), "{}", error);
+    }
+
+    #[test]
+    fn merged_sheets() {
+        let sheets = |second: Vec<Vec<Data>>| {
+            let names = ["May".to_string(), "June".to_string()];
+            let options = SheetOptions {
+                merge_sheets: Some(SheetSet::All),
+                ..SheetOptions::default()
+            };
+            read_sheets(&names, &options, Path::new("covers"), |name| {
+                let rows = match name {
+                    "May" => vec![
+                        vec![text("a.pdf"), Data::Int(1)],
+                        vec![text(""), text("note")],
+                        vec![text("c.pdf"), Data::Int(1)],
+                        vec![text("c.pdf"), Data::Int(2)],
+                    ],
+                    _ => second.clone(),
+                };
+                Ok((rows, (0, 0)))
+            })
+        };
+
+        let (mappings, report) = sheets(vec![
+            vec![text("b.pdf"), Data::Int(2)],
+            vec![text("a.pdf"), Data::Int(1)],
+        ])
+        .unwrap();
+        assert_eq!(mappings.len(), 3);
+        assert_eq!(report.sheets.len(), 2);
+        assert_eq!(report.mapped_rows, 5);
+        assert_eq!(report.ignored_rows, [2]);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.rows["a.pdf"], [1, 2]);
+        assert_eq!(report.rows["b.pdf"], [1]);
+
+        let error = sheets(vec![vec![text("a.pdf"), Data::Int(3)]])
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("a.pdf: page 1 in sheet 'May', page 3 in sheet 'June'"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn mapping_keys() {
+        assert_eq!(mapping_key("hoa.pdf"), "hoa.pdf");
+        assert_eq!(mapping_key("customerA\\hoa.pdf"), "customerA/hoa.pdf");
+        assert_eq!(mapping_key("./customerA/hoa.pdf"), "customerA/hoa.pdf");
+        assert_eq!(mapping_key("/srv/scans/hoa.pdf"), "hoa.pdf");
+        assert_eq!(mapping_key("C:\\scans\\hoa.pdf"), "hoa.pdf");
+    }
+}
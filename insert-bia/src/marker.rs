@@ -0,0 +1,188 @@
+// Marker recorded in a processed file's document info dictionary, so a file
+// that already received its cover page is not given a second one.
+
+use crate::blank::BlankPage;
+use crate::pages::{format_pages, parse_pages};
+use crate::position::Position;
+use lopdf::{decode_text_string, text_string, Dictionary, Document, Object};
+use std::path::Path;
+
+// Document info key holding the (first) 1-based bia.pdf page that was
+// inserted, 0 when pages were only removed
+pub const MARKER_KEY: &[u8] = b"InsertBiaPage";
+// All inserted pages ("3-5"), when there was more than one
+pub const PAGES_KEY: &[u8] = b"InsertBiaPages";
+// Where they were inserted; files marked before positions existed lack it
+pub const POSITION_KEY: &[u8] = b"InsertBiaPosition";
+// Pages of the original that were deleted ("2,7"), when there were any
+pub const REMOVED_KEY: &[u8] = b"InsertBiaRemoved";
+// Where a generated blank page went ("before", "after" or "instead"), when
+// one was inserted
+pub const BLANK_KEY: &[u8] = b"InsertBiaBlank";
+// The text stamped across the pages, when there was a watermark
+pub const WATERMARK_KEY: &[u8] = b"InsertBiaWatermark";
+// The Bates numbers stamped on the pages ("ACME000001-ACME000012"), when
+// they were numbered
+pub const BATES_KEY: &[u8] = b"InsertBiaBates";
+// Title of the bookmark added for the cover, when there was one
+pub const BOOKMARK_KEY: &[u8] = b"InsertBiaBookmark";
+// Whether the original pages were given page numbers
+pub const PAGE_NUMBERS_KEY: &[u8] = b"InsertBiaPageNumbers";
+// Whether form fields and annotations were flattened into the pages
+pub const FLATTENED_KEY: &[u8] = b"InsertBiaFlattened";
+
+#[derive(Debug, Clone)]
+pub struct Marker {
+    // 1-based bia.pdf pages, in the order they were inserted
+    pub pages: Vec<u32>,
+    pub position: Position,
+    // 1-based pages of the original that were deleted
+    pub removed: Vec<u32>,
+    pub blank: Option<BlankPage>,
+    pub watermark: Option<String>,
+    pub bates: Option<String>,
+    pub page_numbers: bool,
+    pub bookmark: Option<String>,
+    pub flattened: bool,
+}
+
+// The insertion recorded in `pdf_path`, if any. Files that cannot be parsed
+// are reported as unmarked and left for qpdf to deal with.
+pub fn read_marker(pdf_path: &Path) -> Option<Marker> {
+    read_marker_with_password(pdf_path, None)
+}
+
+// The same for a file that is opened with `password`
+pub fn read_marker_with_password(pdf_path: &Path, password: Option<&str>) -> Option<Marker> {
+    let metadata = match password {
+        Some(password) => Document::load_metadata_with_password(pdf_path, password),
+        None => Document::load_metadata(pdf_path),
+    }
+    .ok()?;
+    let page_number = match metadata.custom.get(MARKER_KEY)? {
+        Object::Integer(page) => u32::try_from(*page).ok()?,
+        _ => return None,
+    };
+    let pages = match metadata.custom.get(PAGES_KEY) {
+        Some(Object::String(bytes, _)) => parse_pages(&String::from_utf8_lossy(bytes)).ok()?,
+        _ if page_number == 0 => Vec::new(),
+        _ => vec![page_number],
+    };
+    let position = match metadata.custom.get(POSITION_KEY) {
+        Some(Object::String(bytes, _)) => String::from_utf8_lossy(bytes).parse().ok()?,
+        _ => Position::Front,
+    };
+    let removed = match metadata.custom.get(REMOVED_KEY) {
+        Some(Object::String(bytes, _)) => parse_pages(&String::from_utf8_lossy(bytes)).ok()?,
+        _ => Vec::new(),
+    };
+    let blank = match metadata.custom.get(BLANK_KEY) {
+        Some(Object::String(bytes, _)) => Some(String::from_utf8_lossy(bytes).parse().ok()?),
+        _ => None,
+    };
+    let text = |key: &[u8]| decode_text_string(metadata.custom.get(key)?).ok();
+    let flag = |key: &[u8]| matches!(metadata.custom.get(key), Some(Object::Boolean(true)));
+
+    Some(Marker {
+        pages,
+        position,
+        removed,
+        blank,
+        watermark: text(WATERMARK_KEY),
+        bates: text(BATES_KEY),
+        page_numbers: flag(PAGE_NUMBERS_KEY),
+        bookmark: text(BOOKMARK_KEY),
+        flattened: flag(FLATTENED_KEY),
+    })
+}
+
+// The document info dictionary of `doc`, created if the file has none
+pub(crate) fn info_dict_mut(doc: &mut Document) -> lopdf::Result<&mut Dictionary> {
+    let id = match doc.trailer.get(b"Info") {
+        Ok(Object::Reference(id)) => *id,
+        Ok(Object::Dictionary(_)) => return doc.trailer.get_mut(b"Info")?.as_dict_mut(),
+        _ => {
+            let id = doc.add_object(Dictionary::new());
+            doc.trailer.set("Info", id);
+            id
+        }
+    };
+    doc.get_dictionary_mut(id)
+}
+
+// Record `marker` in the document info of `pdf_path`, rewriting the file
+pub fn write_marker(pdf_path: &Path, marker: &Marker) -> Result<(), Box<dyn std::error::Error>> {
+    let first_page = marker.pages.first().copied().unwrap_or_default();
+
+    let mut doc = Document::load(pdf_path)?;
+    let info = info_dict_mut(&mut doc)?;
+    info.set(MARKER_KEY, Object::Integer(first_page.into()));
+    if marker.pages.len() > 1 {
+        info.set(
+            PAGES_KEY,
+            Object::string_literal(format_pages(&marker.pages)),
+        );
+    } else {
+        info.remove(PAGES_KEY);
+    }
+    info.set(
+        POSITION_KEY,
+        Object::string_literal(marker.position.to_string()),
+    );
+    if marker.removed.is_empty() {
+        info.remove(REMOVED_KEY);
+    } else {
+        info.set(
+            REMOVED_KEY,
+            Object::string_literal(format_pages(&marker.removed)),
+        );
+    }
+    match marker.blank {
+        Some(blank) => info.set(BLANK_KEY, Object::string_literal(blank.to_string())),
+        None => {
+            info.remove(BLANK_KEY);
+        }
+    }
+    for (key, text) in [
+        (WATERMARK_KEY, &marker.watermark),
+        (BATES_KEY, &marker.bates),
+        (BOOKMARK_KEY, &marker.bookmark),
+    ] {
+        match text {
+            Some(text) => info.set(key, text_string(text)),
+            None => {
+                info.remove(key);
+            }
+        }
+    }
+    for (key, flag) in [
+        (PAGE_NUMBERS_KEY, marker.page_numbers),
+        (FLATTENED_KEY, marker.flattened),
+    ] {
+        if flag {
+            info.set(key, Object::Boolean(true));
+        } else {
+            info.remove(key);
+        }
+    }
+    doc.save(pdf_path)?;
+    Ok(())
+}
+
+// Remove the marker from `pdf_path`, rewriting the file
+pub fn clear_marker(pdf_path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(pdf_path)?;
+    let info = info_dict_mut(&mut doc)?;
+    info.remove(MARKER_KEY);
+    info.remove(PAGES_KEY);
+    info.remove(POSITION_KEY);
+    info.remove(REMOVED_KEY);
+    info.remove(BLANK_KEY);
+    info.remove(WATERMARK_KEY);
+    info.remove(BATES_KEY);
+    info.remove(PAGE_NUMBERS_KEY);
+    info.remove(BOOKMARK_KEY);
+    info.remove(FLATTENED_KEY);
+    doc.save(pdf_path)?;
+    Ok(())
+}
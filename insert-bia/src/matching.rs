@@ -0,0 +1,647 @@
+use crate::mapping::output_file_name;
+use crate::pages::parse_pages;
+use crate::position::Position;
+use glob::{MatchOptions, Pattern};
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// What of a PDF's path is matched against the Excel keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MatchBy {
+    // The file name
+    #[default]
+    Name,
+    // The name of the folder the file is in, so every PDF in a customer's
+    // folder gets that customer's page
+    Folder,
+    // The document's /Title, for files with machine-generated names
+    Title,
+    // The text of the first page, searched for a key or a pattern
+    Text,
+    // A barcode or QR code on the first page
+    Barcode,
+}
+
+// When a matching rule is tried, relative to the built-in matching
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RuleStage {
+    // Before the Excel keys, overriding them
+    #[default]
+    Before,
+    // Only for files the Excel keys do not match
+    After,
+}
+
+// What a matching rule maps a file name to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleTarget {
+    // An Excel key, with $1, ${name} etc. replaced by the pattern's groups
+    Key(String),
+    // 1-based cover pages, without looking at the Excel file
+    Pages(Vec<u32>),
+}
+
+// A regular expression on file names and what the files it matches map to
+#[derive(Debug, Clone)]
+pub struct MatchRule {
+    pub pattern: Regex,
+    pub target: RuleTarget,
+    pub stage: RuleStage,
+    // Replace what the entry says for the files the rule matches
+    pub cover: Option<PathBuf>,
+    pub position: Option<Position>,
+    // New file name, with $1, ${name} etc. replaced by the pattern's groups
+    pub output_name: Option<String>,
+}
+
+impl MatchRule {
+    pub fn new(pattern: &str, target: RuleTarget, stage: RuleStage) -> Result<Self, String> {
+        let pattern =
+            Regex::new(pattern).map_err(|e| format!("invalid pattern '{}': {}", pattern, e))?;
+        if target == RuleTarget::Pages(Vec::new()) {
+            return Err(format!("rule '{}' maps to no pages", pattern));
+        }
+        Ok(MatchRule {
+            pattern,
+            target,
+            stage,
+            cover: None,
+            position: None,
+            output_name: None,
+        })
+    }
+
+    pub fn with_output_name(mut self, template: &str) -> Result<Self, String> {
+        output_file_name(template).map_err(|e| format!("rule '{}': {}", self.pattern, e))?;
+        self.output_name = Some(template.to_string());
+        Ok(self)
+    }
+
+    // What `pdf_filename` maps to if the pattern matches it: the expanded
+    // Excel key, or the pages
+    pub fn apply(&self, pdf_filename: &str) -> Option<RuleTarget> {
+        let captures = self.pattern.captures(pdf_filename)?;
+        Some(match &self.target {
+            RuleTarget::Key(template) => {
+                let mut key = String::new();
+                captures.expand(template, &mut key);
+                RuleTarget::Key(key)
+            }
+            RuleTarget::Pages(pages) => RuleTarget::Pages(pages.clone()),
+        })
+    }
+
+    // The file name the rule gives `pdf_filename`, None without one or when
+    // the groups make it a path
+    pub fn expand_output_name(&self, pdf_filename: &str) -> Option<String> {
+        let template = self.output_name.as_ref()?;
+        let captures = self.pattern.captures(pdf_filename)?;
+        let mut name = String::new();
+        captures.expand(template, &mut name);
+        output_file_name(&name).ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct TypoSuggestion {
+    pub pdf_path: PathBuf,
+    pub excel_key: String,
+    pub distance: usize,
+}
+
+pub fn normalize_filename(filename: &str) -> String {
+    // Remove path, keep only filename
+    let filename_only = Path::new(filename)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(filename)
+        .to_string();
+
+    // Remove .pdf extension for comparison
+    filename_only
+        .strip_suffix(".pdf")
+        .or_else(|| filename_only.strip_suffix(".PDF"))
+        .unwrap_or(&filename_only)
+        .to_string()
+}
+
+// Which copies of a mapped file match its Excel key. The original always
+// matches; copies are the names browsers give a file saved twice, and with
+// `NameOptions::copy_suffixes` those of Windows and scanners (see `copy_of`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyPolicy {
+    // Every copy
+    All,
+    // Only the first copy: "hoa (1).pdf" (or "hoa - Copy.pdf", "hoa_1.pdf")
+    #[default]
+    First,
+    // No copies
+    Original,
+    // Every copy, and the job refuses to start when there is one
+    Error,
+}
+
+// The name of the file `pdf_filename` is a copy of, and the copy's number:
+// "hoa (2).pdf" -> ("hoa", 2). With `suffixes`, also "hoa - Copy.pdf" ->
+// ("hoa", 1), "hoa - Copy (3).pdf" -> ("hoa", 3) and "hoa_1.pdf" -> ("hoa",
+// 1); without, those are names of their own, as "HD_1.pdf" often is.
+pub fn copy_of(pdf_filename: &str, suffixes: bool) -> Option<(String, u32)> {
+    let name = normalize_filename(pdf_filename);
+    let number = |digits: &str| {
+        digits
+            .parse::<u32>()
+            .ok()
+            .filter(|&n| n > 0 && digits.chars().all(|c| c.is_ascii_digit()))
+    };
+
+    let (original, copy) = if let Some(rest) = name.strip_suffix(')') {
+        let (stem, digits) = rest.rsplit_once('(')?;
+        let copy = number(digits)?;
+        let stem = stem.trim_end();
+        // Windows numbers its copies "hoa - Copy (2)"
+        match stem.strip_suffix(" - Copy") {
+            Some(original) if suffixes => (original, copy),
+            _ => (stem, copy),
+        }
+    } else if !suffixes {
+        return None;
+    } else if let Some(stem) = name.strip_suffix(" - Copy") {
+        (stem, 1)
+    } else {
+        let (stem, digits) = name.rsplit_once('_')?;
+        (stem, number(digits)?)
+    };
+
+    let original = original.trim();
+    if original.is_empty() {
+        return None;
+    }
+    Some((original.to_string(), copy))
+}
+
+// The original's name if `pdf_filename` is a copy the policy lets match
+fn matching_copy_of(pdf_filename: &str, options: &NameOptions) -> Option<String> {
+    let (original, copy) = copy_of(pdf_filename, options.copy_suffixes)?;
+    let matches = match options.copies {
+        CopyPolicy::All | CopyPolicy::Error => true,
+        CopyPolicy::First => copy == 1,
+        CopyPolicy::Original => false,
+    };
+    matches.then_some(original)
+}
+
+// How loosely file names are compared with Excel keys
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NameOptions {
+    pub copies: CopyPolicy,
+    // "- Copy" and "_N" names count as copies too, not only "(N)"
+    pub copy_suffixes: bool,
+    pub ignore_case: bool,
+    // Runs of spaces count as one, and leading or trailing ones are ignored
+    pub collapse_whitespace: bool,
+    // "-", "_" and " " are the same character (whitespace is then collapsed too)
+    pub equivalent_separators: bool,
+    // Letters are compared without accents, as their closest ASCII ("Báo
+    // cáo" is "Bao cao", "Đ" is "D")
+    pub transliterate: bool,
+}
+
+impl NameOptions {
+    // Whether names must match exactly, as by default
+    pub fn is_exact(&self) -> bool {
+        !self.ignore_case
+            && !self.collapse_whitespace
+            && !self.equivalent_separators
+            && !self.transliterate
+    }
+
+    // `name` reduced so that names these options consider equal are equal
+    pub fn fold(&self, name: &str) -> String {
+        let mut folded = name.to_string();
+        if self.transliterate {
+            folded = deunicode::deunicode(&folded);
+        }
+        if self.ignore_case {
+            folded = folded.to_lowercase();
+        }
+        if self.equivalent_separators {
+            folded = folded.replace(['-', '_'], " ");
+        }
+        if self.collapse_whitespace || self.equivalent_separators {
+            folded = folded.split_whitespace().collect::<Vec<_>>().join(" ");
+        }
+        folded
+    }
+}
+
+pub fn match_pdf_name_with_key<T: Clone>(
+    pdf_filename: &str,
+    mappings: &HashMap<String, T>,
+    options: &NameOptions,
+) -> Option<(T, String)> {
+    let pdf_base = normalize_filename(pdf_filename);
+
+    // Try exact match first: "hoa" matches "hoa"
+    if let Some(page) = mappings.get(&pdf_base) {
+        return Some((page.clone(), pdf_base));
+    }
+
+    // Try with .pdf extension: "hoa" matches "hoa.pdf"
+    let pdf_with_ext = format!("{}.pdf", pdf_base);
+    if let Some(page) = mappings.get(&pdf_with_ext) {
+        return Some((page.clone(), pdf_with_ext));
+    }
+
+    // Copies of a mapped file ("hoa (1).pdf") match its key as the policy
+    // allows
+    if let Some(pdf_base_name) = matching_copy_of(pdf_filename, options) {
+        // "hoa (1).pdf" is a copy of "hoa", matches Excel "hoa"
+        if let Some(page) = mappings.get(&pdf_base_name) {
+            return Some((page.clone(), pdf_base_name));
+        }
+
+        // Excel "hoa.pdf"
+        let with_ext = format!("{}.pdf", pdf_base_name);
+        if let Some(page) = mappings.get(&with_ext) {
+            return Some((page.clone(), with_ext));
+        }
+    }
+
+    if !options.is_exact() {
+        if let Some(found) = match_folded_name(pdf_filename, mappings, options) {
+            return Some(found);
+        }
+    }
+
+    // Wildcard keys last: "HD2024-03*" matches "HD2024-03-001.pdf"
+    match_wildcard_key(pdf_filename, &pdf_base, mappings, options)
+}
+
+// The same comparisons as the exact ones, on names folded by `options`. Keys
+// are tried in order so the result does not depend on map order.
+fn match_folded_name<T: Clone>(
+    pdf_filename: &str,
+    mappings: &HashMap<String, T>,
+    options: &NameOptions,
+) -> Option<(T, String)> {
+    let mut keys: Vec<&String> = mappings
+        .keys()
+        .filter(|key| !is_wildcard_key(key) && !is_path_key(key))
+        .collect();
+    keys.sort();
+
+    let pdf_base = options.fold(&normalize_filename(pdf_filename));
+    let found = keys
+        .iter()
+        .find(|key| options.fold(&normalize_filename(key)) == pdf_base)
+        .or_else(|| {
+            let pdf_base_name = options.fold(&matching_copy_of(pdf_filename, options)?);
+            keys.iter()
+                .find(|key| options.fold(&normalize_filename(key)) == pdf_base_name)
+        })?;
+
+    Some((mappings[*found].clone(), found.to_string()))
+}
+
+// Whether an Excel key is a relative path ("customerA/hoa.pdf"), matched
+// against the file's path rather than its name
+pub fn is_path_key(key: &str) -> bool {
+    key.contains('/')
+}
+
+// The path key matching `relative`, the file's path under its base directory.
+// A key matches the whole path or its last components, with or without the
+// .pdf extension; wildcards in a key do not cross a "/". The longest key wins.
+pub fn match_pdf_path_with_key<T: Clone>(
+    relative: &Path,
+    mappings: &HashMap<String, T>,
+) -> Option<(T, String)> {
+    let relative = relative.to_string_lossy().replace('\\', "/");
+    let without_ext = strip_pdf_extension(&relative);
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let ends_with = |path: &str, key: &str| {
+        path == key
+            || path
+                .strip_suffix(key)
+                .is_some_and(|rest| rest.ends_with('/'))
+    };
+
+    mappings
+        .iter()
+        .filter(|(key, _)| is_path_key(key))
+        .filter(|(key, _)| {
+            if is_wildcard_key(key) {
+                // Anchored at a component boundary like plain keys
+                let pattern = format!("**/{}", key);
+                [key.as_str(), pattern.as_str()].iter().any(|pattern| {
+                    Pattern::new(pattern).is_ok_and(|pattern| {
+                        pattern.matches_with(&relative, options)
+                            || pattern.matches_with(without_ext, options)
+                    })
+                })
+            } else {
+                ends_with(&relative, key) || ends_with(without_ext, strip_pdf_extension(key))
+            }
+        })
+        .min_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)))
+        .map(|(key, page)| (page.clone(), key.clone()))
+}
+
+fn strip_pdf_extension(name: &str) -> &str {
+    name.strip_suffix(".pdf")
+        .or_else(|| name.strip_suffix(".PDF"))
+        .unwrap_or(name)
+}
+
+// Whether an Excel key is a glob pattern rather than a filename
+pub fn is_wildcard_key(key: &str) -> bool {
+    key.contains(['*', '?', '['])
+}
+
+// The wildcard key matching the file name with or without its .pdf
+// extension. When several do, the longest (most specific) pattern wins, then
+// the alphabetically first, so the choice does not depend on map order.
+fn match_wildcard_key<T: Clone>(
+    pdf_filename: &str,
+    pdf_base: &str,
+    mappings: &HashMap<String, T>,
+    options: &NameOptions,
+) -> Option<(T, String)> {
+    let match_options = MatchOptions {
+        case_sensitive: !options.ignore_case,
+        ..MatchOptions::new()
+    };
+    mappings
+        .iter()
+        .filter(|(key, _)| is_wildcard_key(key) && !is_path_key(key))
+        .filter(|(key, _)| {
+            Pattern::new(key).is_ok_and(|pattern| {
+                pattern.matches_with(pdf_filename, match_options)
+                    || pattern.matches_with(pdf_base, match_options)
+            })
+        })
+        .min_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)))
+        .map(|(key, page)| (page.clone(), key.clone()))
+}
+
+// The keys that could match `pdf_filename`, sorted: the keys that are its
+// name exactly, or else every key that matches it at all (as a copy, once
+// folded or as a wildcard), whichever the matcher would prefer. Path keys are
+// not considered.
+pub fn candidate_keys<T>(
+    pdf_filename: &str,
+    mappings: &HashMap<String, T>,
+    options: &NameOptions,
+) -> Vec<String> {
+    let pdf_base = normalize_filename(pdf_filename);
+    let copy_of = matching_copy_of(pdf_filename, options);
+    let match_options = MatchOptions {
+        case_sensitive: !options.ignore_case,
+        ..MatchOptions::new()
+    };
+    let name_keys = || mappings.keys().filter(|key| !is_path_key(key));
+
+    let mut keys: Vec<String> = name_keys()
+        .filter(|key| !is_wildcard_key(key) && normalize_filename(key) == pdf_base)
+        .cloned()
+        .collect();
+    if keys.is_empty() {
+        let folded_base = options.fold(&pdf_base);
+        let folded_copy = copy_of.as_deref().map(|name| options.fold(name));
+        keys = name_keys()
+            .filter(|key| {
+                if is_wildcard_key(key) {
+                    return Pattern::new(key).is_ok_and(|pattern| {
+                        pattern.matches_with(pdf_filename, match_options)
+                            || pattern.matches_with(&pdf_base, match_options)
+                    });
+                }
+                let key_base = options.fold(&normalize_filename(key));
+                key_base == folded_base || folded_copy.as_ref() == Some(&key_base)
+            })
+            .cloned()
+            .collect();
+    }
+    keys.sort();
+    keys
+}
+
+// The pages `pattern` finds in `pdf_filename` ("contract__p7.pdf" gives 7
+// with "__p(\d+)"): what its first group, or the whole match, captures, as a
+// page list. Returned with the captured text.
+pub fn pages_in_name(pdf_filename: &str, pattern: &Regex) -> Option<(Vec<u32>, String)> {
+    let captures = pattern.captures(pdf_filename)?;
+    let found = captures.get(1).or_else(|| captures.get(0))?.as_str();
+    let pages = parse_pages(found).ok()?;
+    Some((pages, found.to_string()))
+}
+
+// The longest key found in `text`, compared as `options` compares names.
+// Wildcard and path keys are not looked for.
+pub fn find_key_in_text<T>(
+    text: &str,
+    mappings: &HashMap<String, T>,
+    options: &NameOptions,
+) -> Option<String> {
+    let text = options.fold(text);
+    mappings
+        .keys()
+        .filter(|key| !is_wildcard_key(key) && !is_path_key(key))
+        .filter(|key| {
+            let key = options.fold(&normalize_filename(key));
+            !key.is_empty() && text.contains(&key)
+        })
+        .min_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)))
+        .cloned()
+}
+
+// The first run of at least `min_digits` digits in `name`, without leading
+// zeros: "scan_000123_final.pdf" -> "123"
+pub fn numeric_id(name: &str, min_digits: usize) -> Option<String> {
+    let name = normalize_filename(name);
+    let id = name
+        .split(|c: char| !c.is_ascii_digit())
+        .find(|digits| digits.len() >= min_digits.max(1))?;
+    let id = id.trim_start_matches('0');
+    Some(if id.is_empty() { "0" } else { id }.to_string())
+}
+
+// The key with the same numeric ID as the file name. Keys are tried in order;
+// when several keys have the ID nothing matches, as either could be meant.
+pub fn match_numeric_id<T: Clone>(
+    pdf_filename: &str,
+    mappings: &HashMap<String, T>,
+    min_digits: usize,
+) -> Option<(T, String)> {
+    let id = numeric_id(pdf_filename, min_digits)?;
+    let mut keys = mappings
+        .keys()
+        .filter(|key| !is_wildcard_key(key) && !is_path_key(key))
+        .filter(|key| numeric_id(key, min_digits).as_ref() == Some(&id));
+
+    let key = keys.next()?;
+    if keys.next().is_some() {
+        return None;
+    }
+    Some((mappings[key].clone(), key.clone()))
+}
+
+// The key most similar to the file name (Jaro-Winkler similarity of the names
+// without extension, ignoring case) if it reaches `threshold` (0 to 1), with
+// that similarity. For files no other rule matched; wildcard and path keys
+// are not considered.
+pub fn match_fuzzy_key<T: Clone>(
+    pdf_filename: &str,
+    mappings: &HashMap<String, T>,
+    threshold: f64,
+) -> Option<(T, String, f64)> {
+    let pdf_base = normalize_filename(pdf_filename).to_lowercase();
+
+    mappings
+        .iter()
+        .filter(|(key, _)| !is_wildcard_key(key) && !is_path_key(key))
+        .map(|(key, value)| {
+            let similarity =
+                strsim::jaro_winkler(&pdf_base, &normalize_filename(key).to_lowercase());
+            (key, value, similarity)
+        })
+        .filter(|(_, _, similarity)| *similarity >= threshold)
+        // Most similar first, ties alphabetically for stable results
+        .min_by(|a, b| b.2.total_cmp(&a.2).then_with(|| a.0.cmp(b.0)))
+        .map(|(key, value, similarity)| (value.clone(), key.clone(), similarity))
+}
+
+pub fn audit_typos<T: Clone>(
+    pdf_files: &[PathBuf],
+    mappings: &HashMap<String, T>,
+    max_distance: usize,
+) -> Vec<TypoSuggestion> {
+    let mut suggestions = Vec::new();
+
+    for pdf_path in pdf_files {
+        let filename = match pdf_path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name,
+            None => continue,
+        };
+
+        // Only files the matcher would skip are typo candidates
+        if match_pdf_name_with_key(filename, mappings, &NameOptions::default()).is_some() {
+            continue;
+        }
+
+        let pdf_base = normalize_filename(filename);
+
+        // Pick the nearest Excel key; ties resolve alphabetically for stable output
+        let nearest = mappings
+            .keys()
+            .filter(|key| !is_wildcard_key(key))
+            .map(|key| {
+                (
+                    strsim::levenshtein(&pdf_base, &normalize_filename(key)),
+                    key,
+                )
+            })
+            .filter(|(distance, _)| *distance > 0 && *distance <= max_distance)
+            .min_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.cmp(b.1)));
+
+        if let Some((distance, key)) = nearest {
+            suggestions.push(TypoSuggestion {
+                pdf_path: pdf_path.clone(),
+                excel_key: key.clone(),
+                distance,
+            });
+        }
+    }
+
+    suggestions.sort_by(|a, b| a.pdf_path.cmp(&b.pdf_path));
+    suggestions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn copy(original: &str, number: u32) -> Option<(String, u32)> {
+        Some((original.to_string(), number))
+    }
+
+    #[test]
+    fn numbered_copies() {
+        for suffixes in [false, true] {
+            assert_eq!(copy_of("hoa (1).pdf", suffixes), copy("hoa", 1));
+            assert_eq!(copy_of("hoa (2).pdf", suffixes), copy("hoa", 2));
+            assert_eq!(copy_of("hoa(3).PDF", suffixes), copy("hoa", 3));
+        }
+    }
+
+    #[test]
+    fn windows_and_scanner_copies_need_suffixes() {
+        assert_eq!(copy_of("hoa - Copy.pdf", true), copy("hoa", 1));
+        assert_eq!(copy_of("hoa - Copy (3).pdf", true), copy("hoa", 3));
+        assert_eq!(copy_of("hoa_1.pdf", true), copy("hoa", 1));
+        assert_eq!(copy_of("HD_12.pdf", true), copy("HD", 12));
+
+        assert_eq!(copy_of("hoa - Copy.pdf", false), None);
+        assert_eq!(copy_of("hoa_1.pdf", false), None);
+        // Only the number is taken off
+        assert_eq!(copy_of("hoa - Copy (3).pdf", false), copy("hoa - Copy", 3));
+    }
+
+    #[test]
+    fn names_that_are_not_copies() {
+        for suffixes in [false, true] {
+            assert_eq!(copy_of("hoa.pdf", suffixes), None);
+            assert_eq!(copy_of("hoa (0).pdf", suffixes), None);
+            assert_eq!(copy_of("hoa (draft).pdf", suffixes), None);
+            assert_eq!(copy_of("hoa (+1).pdf", suffixes), None);
+            assert_eq!(copy_of("(1).pdf", suffixes), None);
+            assert_eq!(copy_of("hoa).pdf", suffixes), None);
+        }
+        assert_eq!(copy_of("hoa_v2.pdf", true), None);
+        assert_eq!(copy_of("hoa_.pdf", true), None);
+        assert_eq!(copy_of("_1.pdf", true), None);
+    }
+
+    #[test]
+    fn default_matches_only_the_first_numbered_copy() {
+        let mappings = HashMap::from([("HD".to_string(), 1), ("hoa.pdf".to_string(), 2)]);
+        let options = NameOptions::default();
+        let key =
+            |filename| match_pdf_name_with_key(filename, &mappings, &options).map(|(_, key)| key);
+
+        assert_eq!(key("HD.pdf"), Some("HD".to_string()));
+        assert_eq!(key("HD (1).pdf"), Some("HD".to_string()));
+        assert_eq!(key("hoa (1).pdf"), Some("hoa.pdf".to_string()));
+        assert_eq!(key("HD (2).pdf"), None);
+        // A file of its own, not a copy of "HD"
+        assert_eq!(key("HD_1.pdf"), None);
+        assert_eq!(key("HD - Copy.pdf"), None);
+    }
+
+    #[test]
+    fn policies_choose_the_copies() {
+        let mappings = HashMap::from([("HD".to_string(), 1)]);
+        let matches = |filename, copies, copy_suffixes| {
+            let options = NameOptions {
+                copies,
+                copy_suffixes,
+                ..NameOptions::default()
+            };
+            match_pdf_name_with_key(filename, &mappings, &options).is_some()
+        };
+
+        assert!(matches("HD_1.pdf", CopyPolicy::First, true));
+        assert!(matches("HD - Copy.pdf", CopyPolicy::First, true));
+        assert!(!matches("HD_2.pdf", CopyPolicy::First, true));
+        assert!(matches("HD_2.pdf", CopyPolicy::All, true));
+        assert!(matches("HD (2).pdf", CopyPolicy::All, false));
+        assert!(matches("HD (2).pdf", CopyPolicy::Error, false));
+        assert!(!matches("HD (1).pdf", CopyPolicy::Original, false));
+        assert!(!matches("HD_1.pdf", CopyPolicy::Original, true));
+        assert!(matches("HD.pdf", CopyPolicy::Original, true));
+    }
+}
@@ -0,0 +1,217 @@
+// What is read from the PDFs being processed to match them: their document
+// info and the text of their first page. Also the document info and XMP
+// metadata carried over to their merged output, and the title, author,
+// subject and keywords set on it from the mapping.
+
+use crate::marker::info_dict_mut;
+use lopdf::{text_string, Document, Object, Stream};
+use std::path::Path;
+
+// The /Title of `pdf_path`, None when it has none or cannot be parsed
+pub fn read_title(pdf_path: &Path) -> Option<String> {
+    let metadata = Document::load_metadata(pdf_path).ok()?;
+    metadata
+        .title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+}
+
+// The text of the first page of `pdf_path`, None when it has none (such as a
+// scan without OCR) or cannot be parsed
+pub fn read_first_page_text(pdf_path: &Path) -> Option<String> {
+    let doc = Document::load(pdf_path).ok()?;
+    let text = doc.extract_text(&[1]).ok()?;
+    Some(text).filter(|text| !text.trim().is_empty())
+}
+
+// Document info entries carried over from a target to its merged output
+const CARRIED_INFO: [&[u8]; 6] = [
+    b"Title",
+    b"Author",
+    b"Subject",
+    b"Keywords",
+    b"Creator",
+    b"CreationDate",
+];
+
+// The document info and XMP packet of a target, for its merged output
+#[derive(Debug, Clone, Default)]
+pub(crate) struct DocumentMetadata {
+    info: Vec<(&'static [u8], Object)>,
+    xmp: Option<Stream>,
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+pub(crate) fn read_document_metadata(doc: &Document) -> DocumentMetadata {
+    let info = doc
+        .trailer
+        .get(b"Info")
+        .ok()
+        .and_then(|info| resolve(doc, info)?.as_dict().ok());
+    let xmp = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| resolve(doc, catalog.get(b"Metadata").ok()?))
+        .and_then(|xmp| xmp.as_stream().ok())
+        .map(|xmp| {
+            let mut xmp = xmp.clone();
+            xmp.dict.remove(b"Length");
+            xmp
+        });
+
+    DocumentMetadata {
+        info: CARRIED_INFO
+            .iter()
+            .filter_map(|&key| {
+                let value = resolve(doc, info?.get(key).ok()?)?;
+                Some((key, value.clone()))
+            })
+            .collect(),
+        xmp,
+    }
+}
+
+// Give `pdf_path` the entries of `metadata` it lacks, rewriting the file only
+// when any were missing. qpdf keeps them when the target is its primary input;
+// this makes sure indexing by title, author or date still finds the file.
+pub(crate) fn restore_document_metadata(
+    pdf_path: &Path,
+    metadata: &DocumentMetadata,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if metadata.info.is_empty() && metadata.xmp.is_none() {
+        return Ok(());
+    }
+    let mut doc = Document::load(pdf_path)?;
+    let mut changed = false;
+
+    let info = info_dict_mut(&mut doc)?;
+    for (key, value) in &metadata.info {
+        if !info.has(key) {
+            info.set(*key, value.clone());
+            changed = true;
+        }
+    }
+
+    if let Some(xmp) = &metadata.xmp {
+        let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+        if !doc.get_dictionary(catalog_id)?.has(b"Metadata") {
+            let xmp_id = doc.add_object(xmp.clone());
+            doc.get_dictionary_mut(catalog_id)?.set("Metadata", xmp_id);
+            changed = true;
+        }
+    }
+
+    if changed {
+        doc.save(pdf_path)?;
+    }
+    Ok(())
+}
+
+// Title, author, subject and keywords given for a file in its row, replacing
+// those of the processed file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DocumentInfo {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+}
+
+impl DocumentInfo {
+    pub fn is_empty(&self) -> bool {
+        self.entries().next().is_none()
+    }
+
+    // The values given, with their document info key and XMP property
+    fn entries(&self) -> impl Iterator<Item = (&'static str, &'static str, &str)> {
+        [
+            ("Title", "dc:title", &self.title),
+            ("Author", "dc:creator", &self.author),
+            ("Subject", "dc:description", &self.subject),
+            ("Keywords", "pdf:Keywords", &self.keywords),
+        ]
+        .into_iter()
+        .filter_map(|(key, property, value)| Some((key, property, value.as_deref()?)))
+    }
+}
+
+// Set the values of `info` in the document info of `pdf_path`, and in its XMP
+// packet where that has the property (readers prefer the XMP when there is
+// one), rewriting the file
+pub(crate) fn set_document_info(
+    pdf_path: &Path,
+    info: &DocumentInfo,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if info.is_empty() {
+        return Ok(());
+    }
+    let mut doc = Document::load(pdf_path)?;
+    let dict = info_dict_mut(&mut doc)?;
+    for (key, _, value) in info.entries() {
+        dict.set(key, text_string(value));
+    }
+
+    let xmp_id = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| catalog.get(b"Metadata").ok())
+        .and_then(|xmp| xmp.as_reference().ok());
+    if let Some(xmp) = xmp_id.and_then(|id| doc.get_object_mut(id).ok()?.as_stream_mut().ok()) {
+        // Left alone when it cannot be read
+        if let Some(mut packet) = xmp
+            .get_plain_content()
+            .ok()
+            .and_then(|content| String::from_utf8(content).ok())
+        {
+            for (_, property, value) in info.entries() {
+                if let Some(updated) = set_xmp_property(&packet, property, value) {
+                    packet = updated;
+                }
+            }
+            xmp.set_plain_content(packet.into_bytes());
+        }
+    }
+
+    doc.save(pdf_path)?;
+    Ok(())
+}
+
+// `xmp` with `value` as the value of `property`, None when it has no such
+// property. Lists (dc:title, dc:creator) get it as their first item.
+fn set_xmp_property(xmp: &str, property: &str, value: &str) -> Option<String> {
+    let value = xml_escape(value);
+    let replace = |start: usize, end: usize| format!("{}{}{}", &xmp[..start], value, &xmp[end..]);
+
+    // As an attribute of rdf:Description
+    let attribute = format!("{}=\"", property);
+    if let Some(start) = xmp.find(&attribute).map(|i| i + attribute.len()) {
+        let end = start + xmp[start..].find('"')?;
+        return Some(replace(start, end));
+    }
+
+    let open = format!("<{}>", property);
+    let close = format!("</{}>", property);
+    let content = xmp.find(&open)? + open.len();
+    let end = content + xmp[content..].find(&close)?;
+    match xmp[content..end].find("<rdf:li") {
+        Some(item) => {
+            let start = content + item + xmp[content + item..end].find('>')? + 1;
+            let end = start + xmp[start..end].find("</rdf:li>")?;
+            Some(replace(start, end))
+        }
+        None => Some(replace(content, end)),
+    }
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
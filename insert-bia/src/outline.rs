@@ -0,0 +1,452 @@
+// A bookmark for the inserted cover in the merged file's outline, so viewers
+// can jump to it (or past it) in large documents. Existing bookmarks point at
+// page objects rather than page numbers, so they need no shifting.
+
+use lopdf::{decode_text_string, dictionary, text_string, Dictionary, Document, Object, ObjectId};
+use std::path::Path;
+
+// Default --bookmark title
+pub const DEFAULT_TITLE: &str = "Bìa";
+
+// Add a top-level bookmark `title` for the page at 0-based `page_index` of
+// `pdf_path`, among the existing ones in page order, rewriting the file
+pub(crate) fn add_bookmark(
+    pdf_path: &Path,
+    title: &str,
+    page_index: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(pdf_path)?;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let page_id = *pages
+        .get(page_index)
+        .ok_or_else(|| format!("the file has no page {}", page_index + 1))?;
+
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let outlines_id = match doc.get_dictionary(catalog_id)?.get(b"Outlines") {
+        Ok(Object::Reference(id)) => *id,
+        _ => {
+            let id = doc.add_object(dictionary! { "Type" => "Outlines", "Count" => 0 });
+            doc.get_dictionary_mut(catalog_id)?.set("Outlines", id);
+            id
+        }
+    };
+
+    // The first top-level bookmark that goes to a later page; the new one is
+    // put before it, or last when there is none
+    let mut next = None;
+    let mut last = None;
+    let mut item = doc
+        .get_dictionary(outlines_id)?
+        .get(b"First")
+        .and_then(Object::as_reference)
+        .ok();
+    while let Some(id) = item {
+        let dict = doc.get_dictionary(id)?;
+        let target =
+            bookmark_page(&doc, dict).and_then(|target| pages.iter().position(|&p| p == target));
+        if target.is_some_and(|target| target > page_index) {
+            next = Some(id);
+            break;
+        }
+        last = Some(id);
+        item = dict.get(b"Next").and_then(Object::as_reference).ok();
+    }
+
+    let mut bookmark = dictionary! {
+        "Title" => text_string(title),
+        "Parent" => outlines_id,
+        "Dest" => vec![page_id.into(), "Fit".into()],
+    };
+    if let Some(prev) = last {
+        bookmark.set("Prev", prev);
+    }
+    if let Some(next) = next {
+        bookmark.set("Next", next);
+    }
+    let bookmark_id = doc.add_object(bookmark);
+
+    match last {
+        Some(prev) => doc.get_dictionary_mut(prev)?.set("Next", bookmark_id),
+        None => doc
+            .get_dictionary_mut(outlines_id)?
+            .set("First", bookmark_id),
+    }
+    match next {
+        Some(next) => doc.get_dictionary_mut(next)?.set("Prev", bookmark_id),
+        None => doc
+            .get_dictionary_mut(outlines_id)?
+            .set("Last", bookmark_id),
+    }
+    let outlines = doc.get_dictionary_mut(outlines_id)?;
+    let count = outlines.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+    // A negative count means the outline is shown collapsed
+    outlines.set("Count", if count < 0 { count - 1 } else { count + 1 });
+
+    doc.save(pdf_path)?;
+    Ok(())
+}
+
+// The page a bookmark goes to, when it names one directly (not through a
+// named destination)
+fn bookmark_page(doc: &Document, bookmark: &Dictionary) -> Option<ObjectId> {
+    let dest = match bookmark.get(b"Dest") {
+        Ok(dest) => dest,
+        Err(_) => {
+            let action = deref(doc, bookmark.get(b"A").ok()?)?.as_dict().ok()?;
+            action.get(b"D").ok()?
+        }
+    };
+    deref(doc, dest)?
+        .as_array()
+        .ok()?
+        .first()?
+        .as_reference()
+        .ok()
+}
+
+fn deref<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+// Remove the top-level bookmarks titled `title` that go to a page `pdf_path`
+// no longer has, left behind when the cover was removed, rewriting the file
+pub(crate) fn remove_bookmark(
+    pdf_path: &Path,
+    title: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut doc = Document::load(pdf_path)?;
+    let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    let catalog_id = doc.trailer.get(b"Root")?.as_reference()?;
+    let Ok(outlines_id) = doc
+        .get_dictionary(catalog_id)?
+        .get(b"Outlines")
+        .and_then(Object::as_reference)
+    else {
+        return Ok(());
+    };
+
+    let mut stale = Vec::new();
+    let mut item = doc
+        .get_dictionary(outlines_id)?
+        .get(b"First")
+        .and_then(Object::as_reference)
+        .ok();
+    while let Some(id) = item {
+        let dict = doc.get_dictionary(id)?;
+        let titled = dict
+            .get(b"Title")
+            .ok()
+            .and_then(|t| decode_text_string(t).ok())
+            .is_some_and(|t| t == title);
+        let gone = bookmark_page(&doc, dict).is_some_and(|page| !pages.contains(&page));
+        if titled && gone {
+            stale.push(id);
+        }
+        item = dict.get(b"Next").and_then(Object::as_reference).ok();
+    }
+    if stale.is_empty() {
+        return Ok(());
+    }
+
+    for id in stale {
+        let dict = doc.get_dictionary(id)?;
+        let prev = dict.get(b"Prev").and_then(Object::as_reference).ok();
+        let next = dict.get(b"Next").and_then(Object::as_reference).ok();
+        match (prev, next) {
+            (Some(prev), Some(next)) => doc.get_dictionary_mut(prev)?.set("Next", next),
+            (Some(prev), None) => {
+                doc.get_dictionary_mut(prev)?.remove(b"Next");
+            }
+            (None, Some(next)) => doc.get_dictionary_mut(outlines_id)?.set("First", next),
+            (None, None) => {
+                doc.get_dictionary_mut(outlines_id)?.remove(b"First");
+            }
+        }
+        match (next, prev) {
+            (Some(next), Some(prev)) => doc.get_dictionary_mut(next)?.set("Prev", prev),
+            (Some(next), None) => {
+                doc.get_dictionary_mut(next)?.remove(b"Prev");
+            }
+            (None, Some(prev)) => doc.get_dictionary_mut(outlines_id)?.set("Last", prev),
+            (None, None) => {
+                doc.get_dictionary_mut(outlines_id)?.remove(b"Last");
+            }
+        }
+        doc.objects.remove(&id);
+
+        let outlines = doc.get_dictionary_mut(outlines_id)?;
+        let count = outlines.get(b"Count").and_then(Object::as_i64).unwrap_or(0);
+        outlines.set(
+            "Count",
+            if count < 0 {
+                count + 1
+            } else {
+                (count - 1).max(0)
+            },
+        );
+    }
+
+    doc.save(pdf_path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::position::Position;
+    use std::fs;
+
+    // A document of `page_count` pages where bookmark "Page N", named
+    // destination "pN" and a link on the page before go to page N (the link
+    // to the first page is on the last)
+    fn outlined(page_count: usize) -> Document {
+        let mut doc = fixtures::pdf(page_count);
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let outlines_id = doc.new_object_id();
+        let ids: Vec<ObjectId> = (0..page_count).map(|_| doc.new_object_id()).collect();
+        let mut dests = Dictionary::new();
+        for (index, &page_id) in pages.iter().enumerate() {
+            let mut bookmark = dictionary! {
+                "Title" => text_string(&format!("Page {}", index + 1)),
+                "Parent" => outlines_id,
+                "Dest" => vec![page_id.into(), "Fit".into()],
+            };
+            if index > 0 {
+                bookmark.set("Prev", ids[index - 1]);
+            }
+            if index + 1 < page_count {
+                bookmark.set("Next", ids[index + 1]);
+            }
+            doc.objects.insert(ids[index], Object::Dictionary(bookmark));
+            dests.set(
+                format!("p{}", index + 1),
+                vec![Object::from(page_id), "Fit".into()],
+            );
+
+            let on = pages[(index + page_count - 1) % page_count];
+            let link_id = doc.add_object(dictionary! {
+                "Type" => "Annot",
+                "Subtype" => "Link",
+                "Rect" => vec![0.into(), 0.into(), 100.into(), 20.into()],
+                "Dest" => vec![page_id.into(), "Fit".into()],
+            });
+            doc.get_dictionary_mut(on)
+                .unwrap()
+                .set("Annots", vec![link_id.into()]);
+        }
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => ids[0],
+                "Last" => ids[page_count - 1],
+                "Count" => page_count as i64,
+            }),
+        );
+        let dests_id = doc.add_object(dests);
+        let catalog = doc.catalog_mut().unwrap();
+        catalog.set("Outlines", outlines_id);
+        catalog.set("Dests", dests_id);
+        doc
+    }
+
+    // Top-level bookmarks in order, with the 0-based index of their page
+    fn bookmarks(doc: &Document) -> Vec<(String, Option<usize>)> {
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let mut found = Vec::new();
+        let mut item = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Outlines"))
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .and_then(|outlines| outlines.get(b"First"))
+            .and_then(Object::as_reference)
+            .ok();
+        while let Some(id) = item {
+            let dict = doc.get_dictionary(id).unwrap();
+            let title = decode_text_string(dict.get(b"Title").unwrap()).unwrap();
+            let page =
+                bookmark_page(doc, dict).and_then(|page| pages.iter().position(|&p| p == page));
+            found.push((title, page));
+            item = dict.get(b"Next").and_then(Object::as_reference).ok();
+        }
+        found
+    }
+
+    // 0-based page index the named destination `name` goes to
+    fn named_page(doc: &Document, name: &str) -> Option<usize> {
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let dests = doc.catalog().ok()?.get(b"Dests").ok()?;
+        let dest = deref(doc, dests)?
+            .as_dict()
+            .ok()?
+            .get(name.as_bytes())
+            .ok()?;
+        let page = deref(doc, dest)?
+            .as_array()
+            .ok()?
+            .first()?
+            .as_reference()
+            .ok()?;
+        pages.iter().position(|&p| p == page)
+    }
+
+    // For each page, the 0-based page index its link goes to
+    fn links(doc: &Document) -> Vec<Option<usize>> {
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        pages
+            .iter()
+            .map(|&page_id| {
+                let annots = doc.get_dictionary(page_id).ok()?.get(b"Annots").ok()?;
+                let link = deref(doc, deref(doc, annots)?.as_array().ok()?.first()?)?;
+                let dest = link.as_dict().ok()?.get(b"Dest").ok()?;
+                let target = dest.as_array().ok()?.first()?.as_reference().ok()?;
+                pages.iter().position(|&p| p == target)
+            })
+            .collect()
+    }
+
+    // Insert one cover page into `outlined(3)` at `position` and check that
+    // what went to the original's page N now goes where `moved` says it is
+    fn check_targets(name: &str, position: Position, moved: [Option<usize>; 3]) {
+        let insertion = fixtures::insertion("target", vec![1], position);
+        let merged = fixtures::insert(name, outlined(3), fixtures::pdf(1), insertion);
+        let titles = ["Page 1", "Page 2", "Page 3"].map(String::from);
+        let expected: Vec<(String, Option<usize>)> = titles.into_iter().zip(moved).collect();
+        assert_eq!(bookmarks(&merged), expected);
+        for (index, page) in moved.iter().enumerate() {
+            assert_eq!(named_page(&merged, &format!("p{}", index + 1)), *page);
+        }
+        // The links on the kept pages, which go to the next page
+        let merged_links = links(&merged);
+        for (index, &on) in moved.iter().enumerate() {
+            let Some(on) = on else { continue };
+            let to = moved[(index + 1) % 3];
+            if to.is_some() {
+                assert_eq!(merged_links[on], to, "link on page {}", index + 1);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn targets_follow_a_cover_inserted_before() {
+        check_targets(
+            "outline-front",
+            Position::Front,
+            [Some(1), Some(2), Some(3)],
+        );
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn targets_follow_a_cover_inserted_after_a_page() {
+        check_targets(
+            "outline-after",
+            Position::After(1),
+            [Some(0), Some(2), Some(3)],
+        );
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn targets_follow_a_cover_inserted_at_the_back() {
+        check_targets("outline-back", Position::Back, [Some(0), Some(1), Some(2)]);
+    }
+
+    #[test]
+    #[ignore = "needs qpdf"]
+    fn targets_follow_a_cover_in_place_of_a_page() {
+        // The replaced page is gone, and so is what went to it
+        check_targets(
+            "outline-replace",
+            Position::Replace,
+            [None, Some(1), Some(2)],
+        );
+    }
+
+    #[test]
+    fn bookmark_is_added_in_page_order() {
+        let dir = fixtures::scratch_dir("outline-add");
+        let path = dir.join("file.pdf");
+        outlined(3).save(&path).unwrap();
+
+        add_bookmark(&path, DEFAULT_TITLE, 1).unwrap();
+        let doc = Document::load(&path).unwrap();
+        let titles: Vec<String> = bookmarks(&doc)
+            .into_iter()
+            .map(|(title, _)| title)
+            .collect();
+        assert_eq!(titles, ["Page 1", "Page 2", DEFAULT_TITLE, "Page 3"]);
+        assert_eq!(bookmarks(&doc)[2].1, Some(1));
+        let outlines = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Outlines"))
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .unwrap();
+        assert_eq!(outlines.get(b"Count").and_then(Object::as_i64).unwrap(), 4);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn bookmark_is_added_to_a_file_without_outline() {
+        let dir = fixtures::scratch_dir("outline-new");
+        let path = dir.join("file.pdf");
+        fixtures::pdf(2).save(&path).unwrap();
+
+        add_bookmark(&path, "Cover", 0).unwrap();
+        let doc = Document::load(&path).unwrap();
+        assert_eq!(bookmarks(&doc), [("Cover".to_string(), Some(0))]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stale_bookmark_is_removed() {
+        let dir = fixtures::scratch_dir("outline-remove");
+        let path = dir.join("file.pdf");
+        let mut doc = outlined(3);
+        // The cover's bookmark, going to a page no longer in the file
+        let gone = doc.add_object(dictionary! { "Type" => "Page" });
+        let outlines_id = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Outlines"))
+            .and_then(Object::as_reference)
+            .unwrap();
+        let first = doc
+            .get_dictionary(outlines_id)
+            .and_then(|outlines| outlines.get(b"First"))
+            .and_then(Object::as_reference)
+            .unwrap();
+        let stale = doc.add_object(dictionary! {
+            "Title" => text_string(DEFAULT_TITLE),
+            "Parent" => outlines_id,
+            "Dest" => vec![gone.into(), "Fit".into()],
+            "Next" => first,
+        });
+        doc.get_dictionary_mut(first).unwrap().set("Prev", stale);
+        let outlines = doc.get_dictionary_mut(outlines_id).unwrap();
+        outlines.set("First", stale);
+        outlines.set("Count", 4);
+        doc.save(&path).unwrap();
+
+        // Bookmarks with another title, or to a page still there, stay
+        remove_bookmark(&path, "Page 1").unwrap();
+        assert_eq!(bookmarks(&Document::load(&path).unwrap()).len(), 4);
+        remove_bookmark(&path, DEFAULT_TITLE).unwrap();
+        let doc = Document::load(&path).unwrap();
+        assert_eq!(
+            bookmarks(&doc),
+            [
+                ("Page 1".to_string(), Some(0)),
+                ("Page 2".to_string(), Some(1)),
+                ("Page 3".to_string(), Some(2)),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
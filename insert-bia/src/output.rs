@@ -0,0 +1,63 @@
+// Console output layer for the binary. Every user-facing line goes through
+// one of the macros below so --quiet and --verbose apply consistently; the
+// library only logs through `log`, whose level is derived from the same
+// setting.
+
+use log::LevelFilter;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    // Summary and failures only
+    Quiet = 0,
+    Normal = 1,
+    // Adds per-file timing and qpdf diagnostics
+    Verbose = 2,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+// Set the output level and initialize the logger to match. RUST_LOG, when
+// set, still overrides the log level.
+pub fn init(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+
+    let level = match verbosity {
+        Verbosity::Quiet => LevelFilter::Error,
+        Verbosity::Normal => LevelFilter::Warn,
+        Verbosity::Verbose => LevelFilter::Debug,
+    };
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .init();
+}
+
+pub fn enabled(verbosity: Verbosity) -> bool {
+    VERBOSITY.load(Ordering::Relaxed) >= verbosity as u8
+}
+
+// Always printed: summaries, failures and fatal errors
+macro_rules! summary {
+    ($($arg:tt)*) => {
+        println!($($arg)*)
+    };
+}
+
+// Regular progress output, hidden by --quiet
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::enabled($crate::output::Verbosity::Normal) {
+            println!($($arg)*);
+        }
+    };
+}
+
+// Extra diagnostics shown only with --verbose
+macro_rules! detail {
+    ($($arg:tt)*) => {
+        if $crate::output::enabled($crate::output::Verbosity::Verbose) {
+            println!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,117 @@
+// Lists of cover pages, as written in a mapping: "3", "3-5" or "2,7,9"
+
+// Most pages one range may list; no cover comes near it, and a typo such as
+// "1-4000000000" must not run the tool out of memory
+const MAX_RANGE_PAGES: u32 = 10_000;
+
+// 1-based page numbers in the order given. A descending range ("5-3") lists
+// its pages backwards, as qpdf does.
+pub fn parse_pages(s: &str) -> Result<Vec<u32>, String> {
+    let mut pages = Vec::new();
+
+    for part in s.split(',').map(str::trim) {
+        let parse = |n: &str| match n.trim().parse::<u32>() {
+            Ok(0) => Err(format!("page 0 in '{}' (pages start at 1)", s.trim())),
+            Ok(page) => Ok(page),
+            Err(_) => Err(format!("invalid page list '{}'", s.trim())),
+        };
+
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse(first)?, parse(last)?);
+                if first.abs_diff(last) >= MAX_RANGE_PAGES {
+                    return Err(format!(
+                        "range '{}' in '{}' is longer than {} pages",
+                        part,
+                        s.trim(),
+                        MAX_RANGE_PAGES
+                    ));
+                }
+                if first <= last {
+                    pages.extend(first..=last);
+                } else {
+                    pages.extend((last..=first).rev());
+                }
+            }
+            None => pages.push(parse(part)?),
+        }
+    }
+
+    Ok(pages)
+}
+
+// The shortest list `parse_pages` reads back as `pages`, which is also valid
+// qpdf page-range syntax: [3, 4, 5, 7] → "3-5,7"
+pub fn format_pages(pages: &[u32]) -> String {
+    let mut parts = Vec::new();
+    let mut index = 0;
+
+    while index < pages.len() {
+        let first = pages[index];
+        let mut last = first;
+        while index + 1 < pages.len() && last.checked_add(1) == Some(pages[index + 1]) {
+            index += 1;
+            last = pages[index];
+        }
+
+        if first == last {
+            parts.push(first.to_string());
+        } else {
+            parts.push(format!("{}-{}", first, last));
+        }
+        index += 1;
+    }
+
+    parts.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_pages_and_ranges() {
+        assert_eq!(parse_pages("3"), Ok(vec![3]));
+        assert_eq!(parse_pages("3-5"), Ok(vec![3, 4, 5]));
+        assert_eq!(parse_pages(" 2, 7 ,9 "), Ok(vec![2, 7, 9]));
+        assert_eq!(parse_pages("1,3-4,2"), Ok(vec![1, 3, 4, 2]));
+        assert_eq!(parse_pages("4-4"), Ok(vec![4]));
+    }
+
+    #[test]
+    fn descending_range_lists_backwards() {
+        assert_eq!(parse_pages("5-3"), Ok(vec![5, 4, 3]));
+    }
+
+    #[test]
+    fn rejects_bad_lists() {
+        assert!(parse_pages("0").unwrap_err().contains("pages start at 1"));
+        assert!(parse_pages("0-2").is_err());
+        assert!(parse_pages("").is_err());
+        assert!(parse_pages("a").is_err());
+        assert!(parse_pages("1,,2").is_err());
+        assert!(parse_pages("-3").is_err());
+        assert!(parse_pages("1-2-3").is_err());
+    }
+
+    #[test]
+    fn rejects_huge_ranges() {
+        assert!(parse_pages("1-4000000000")
+            .unwrap_err()
+            .contains("longer than"));
+        assert!(parse_pages("4000000000-1").is_err());
+        assert_eq!(parse_pages("1-10000").map(|pages| pages.len()), Ok(10_000));
+        assert!(parse_pages("1-10001").is_err());
+    }
+
+    #[test]
+    fn format_reads_back() {
+        assert_eq!(format_pages(&[3, 4, 5, 7]), "3-5,7");
+        assert_eq!(format_pages(&[5, 4, 3]), "5,4,3");
+        assert_eq!(format_pages(&[]), "");
+        assert_eq!(format_pages(&[u32::MAX]), u32::MAX.to_string());
+        for pages in [vec![1], vec![2, 3, 9, 10, 11, 4], vec![7, 1, 2]] {
+            assert_eq!(parse_pages(&format_pages(&pages)), Ok(pages));
+        }
+    }
+}
@@ -0,0 +1,201 @@
+// PDF/A archives among the files to process. qpdf keeps the catalog of the
+// target, its primary input, so the output intent and the XMP packet claiming
+// conformance ("pdfaid:part") survive the merge, and the XMP is put back if
+// not. What the processing adds can still break conformance (a cover with
+// fonts that are not embedded, the standard fonts of stamped text, see-through
+// watermarks in PDF/A-1, encryption), so the output of a PDF/A file is checked
+// for those and flagged in the report.
+
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+// The conformance a file claims in its XMP metadata
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conformance {
+    // "1" to "4"
+    pub part: String,
+    // "A", "B" or "U"; PDF/A-4 has none
+    pub level: Option<String>,
+}
+
+impl fmt::Display for Conformance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PDF/A-{}", self.part)?;
+        if let Some(level) = &self.level {
+            write!(f, "{}", level.to_uppercase())?;
+        }
+        Ok(())
+    }
+}
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+// The value of `property` in an XMP packet, as an attribute or an element
+fn xmp_value(xmp: &str, property: &str) -> Option<String> {
+    let attribute = format!("{}=\"", property);
+    if let Some(start) = xmp.find(&attribute).map(|i| i + attribute.len()) {
+        let end = start + xmp[start..].find('"')?;
+        return Some(xmp[start..end].trim().to_string());
+    }
+    let open = format!("<{}>", property);
+    let start = xmp.find(&open)? + open.len();
+    let end = start + xmp[start..].find('<')?;
+    Some(xmp[start..end].trim().to_string())
+}
+
+// The PDF/A conformance `doc` claims, None when it claims none
+pub(crate) fn claimed(doc: &Document) -> Option<Conformance> {
+    let xmp = doc
+        .catalog()
+        .ok()
+        .and_then(|catalog| resolve(doc, catalog.get(b"Metadata").ok()?))
+        .and_then(|xmp| xmp.as_stream().ok())
+        .and_then(|xmp| xmp.get_plain_content().ok())?;
+    let xmp = String::from_utf8_lossy(&xmp);
+    let part = xmp_value(&xmp, "pdfaid:part").filter(|part| !part.is_empty())?;
+    Some(Conformance {
+        part,
+        level: xmp_value(&xmp, "pdfaid:conformance").filter(|level| !level.is_empty()),
+    })
+}
+
+// The fonts that are not embedded, and whether anything is see-through, in
+// the resources of `doc`'s pages and of the forms they draw
+fn scan_resources(doc: &Document) -> (BTreeSet<String>, bool) {
+    let mut missing = BTreeSet::new();
+    let mut transparent = false;
+
+    let mut pending: Vec<&Dictionary> = Vec::new();
+    for page_id in doc.get_pages().into_values() {
+        if let Ok((resources, inherited)) = doc.get_page_resources(page_id) {
+            pending.extend(resources);
+            pending.extend(
+                inherited
+                    .iter()
+                    .filter_map(|id| doc.get_dictionary(*id).ok()),
+            );
+        }
+    }
+    let mut forms: HashSet<ObjectId> = HashSet::new();
+    while let Some(resources) = pending.pop() {
+        let entries = |key: &[u8]| -> Vec<&Object> {
+            resources
+                .get(key)
+                .ok()
+                .and_then(|dict| resolve(doc, dict)?.as_dict().ok())
+                .map(|dict| dict.iter().map(|(_, value)| value).collect())
+                .unwrap_or_default()
+        };
+
+        for font in entries(b"Font") {
+            let Some(font) = resolve(doc, font).and_then(|f| f.as_dict().ok()) else {
+                continue;
+            };
+            if let Some(name) = unembedded_font(doc, font) {
+                missing.insert(name);
+            }
+        }
+        for state in entries(b"ExtGState") {
+            let Some(state) = resolve(doc, state).and_then(|s| s.as_dict().ok()) else {
+                continue;
+            };
+            let opacity = |key: &[u8]| state.get(key).ok().and_then(|o| o.as_float().ok());
+            let soft_mask = state
+                .get(b"SMask")
+                .is_ok_and(|mask| mask.as_name().map_or(true, |name| name != b"None"));
+            if opacity(b"ca").is_some_and(|ca| ca < 1.0)
+                || opacity(b"CA").is_some_and(|ca| ca < 1.0)
+                || soft_mask
+            {
+                transparent = true;
+            }
+        }
+        for xobject in entries(b"XObject") {
+            let Ok(id) = xobject.as_reference() else {
+                continue;
+            };
+            if !forms.insert(id) {
+                continue;
+            }
+            let Ok(stream) = doc.get_object(id).and_then(Object::as_stream) else {
+                continue;
+            };
+            let is_form = stream
+                .dict
+                .get(b"Subtype")
+                .and_then(Object::as_name)
+                .is_ok_and(|subtype| subtype == b"Form");
+            if let Some(resources) = stream
+                .dict
+                .get(b"Resources")
+                .ok()
+                .filter(|_| is_form)
+                .and_then(|r| resolve(doc, r)?.as_dict().ok())
+            {
+                pending.push(resources);
+            }
+        }
+    }
+    (missing, transparent)
+}
+
+// The name of `font` when its program is not embedded. Type 3 fonts are
+// drawn by the file itself.
+fn unembedded_font(doc: &Document, font: &Dictionary) -> Option<String> {
+    let subtype = font.get(b"Subtype").and_then(Object::as_name).ok()?;
+    if subtype == b"Type3" {
+        return None;
+    }
+    let described = if subtype == b"Type0" {
+        font.get(b"DescendantFonts")
+            .ok()
+            .and_then(|fonts| resolve(doc, fonts)?.as_array().ok()?.first())
+            .and_then(|font| resolve(doc, font)?.as_dict().ok())?
+    } else {
+        font
+    };
+    let embedded = described
+        .get(b"FontDescriptor")
+        .ok()
+        .and_then(|descriptor| resolve(doc, descriptor)?.as_dict().ok())
+        .is_some_and(|descriptor| {
+            [&b"FontFile"[..], b"FontFile2", b"FontFile3"]
+                .iter()
+                .any(|key| descriptor.has(key))
+        });
+    if embedded {
+        return None;
+    }
+    let name = font.get(b"BaseFont").and_then(Object::as_name).ok()?;
+    Some(String::from_utf8_lossy(name).into_owned())
+}
+
+// Why the output `doc` of a file claiming `conformance` no longer conforms,
+// as far as can be told without a full validation
+pub(crate) fn problems(doc: &Document, conformance: &Conformance) -> Vec<String> {
+    let mut problems = Vec::new();
+    if claimed(doc).is_none() {
+        problems.push("its XMP metadata no longer claims PDF/A".to_string());
+    }
+    let has_output_intent = doc
+        .catalog()
+        .is_ok_and(|catalog| catalog.has(b"OutputIntents"));
+    if !has_output_intent {
+        problems.push("it has no output intent".to_string());
+    }
+    let (missing, transparent) = scan_resources(doc);
+    if !missing.is_empty() {
+        let names: Vec<String> = missing.into_iter().collect();
+        problems.push(format!("fonts are not embedded ({})", names.join(", ")));
+    }
+    if transparent && conformance.part == "1" {
+        problems.push("it uses transparency".to_string());
+    }
+    problems
+}
@@ -0,0 +1,179 @@
+use crate::pages::format_pages;
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+// Where the cover page goes in the target document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Position {
+    #[default]
+    Front,
+    Back,
+    // After page N of the original (1-based); past the end means at the back
+    After(u32),
+    // In place of the original's first page, for documents carrying an
+    // outdated cover
+    Replace,
+    // Stamped over or under the original's first pages (one cover page on
+    // each) instead of inserted, for letterheads
+    Overlay,
+    Underlay,
+}
+
+impl FromStr for Position {
+    type Err = String;
+
+    // Accepts "front", "back", "replace", "overlay", "underlay" and "after:N"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match s.as_str() {
+            "front" => Ok(Position::Front),
+            "back" => Ok(Position::Back),
+            "replace" => Ok(Position::Replace),
+            "overlay" => Ok(Position::Overlay),
+            "underlay" => Ok(Position::Underlay),
+            _ => match s.strip_prefix("after:") {
+                Some(n) => n
+                    .trim()
+                    .parse()
+                    .map(Position::After)
+                    .map_err(|_| format!("invalid page number in position '{}'", s)),
+                None => Err(format!(
+                    "invalid position '{}' (expected front, back, replace, overlay, underlay or after:N)",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Position::Front => write!(f, "front"),
+            Position::Back => write!(f, "back"),
+            Position::After(n) => write!(f, "after:{}", n),
+            Position::Replace => write!(f, "replace"),
+            Position::Overlay => write!(f, "overlay"),
+            Position::Underlay => write!(f, "underlay"),
+        }
+    }
+}
+
+// For `#[serde(deserialize_with)]`: a position string, where a missing or
+// empty value means the default
+pub fn deserialize_or_default<'de, D>(deserializer: D) -> Result<Position, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(Position::default()),
+        Some(s) => s.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+// For `#[serde(deserialize_with)]`: a position string, None when missing or
+// empty
+pub fn deserialize_optional<'de, D>(deserializer: D) -> Result<Option<Position>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Option<String> = Option::deserialize(deserializer)?;
+    match value.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(s) => s.parse().map(Some).map_err(serde::de::Error::custom),
+    }
+}
+
+impl Position {
+    // Number of original pages that come before the inserted ones, for a
+    // document with `page_count` pages
+    pub fn pages_before(self, page_count: usize) -> usize {
+        match self {
+            Position::Front | Position::Replace | Position::Overlay | Position::Underlay => 0,
+            Position::Back => page_count,
+            Position::After(n) => (n as usize).min(page_count),
+        }
+    }
+
+    // Number of original pages that come before the inserted ones once the
+    // `removed` pages are left out
+    pub fn kept_pages_before(self, page_count: usize, removed: &[u32]) -> usize {
+        (1..=self.pages_before(page_count) as u32)
+            .filter(|page| !removed.contains(page))
+            .count()
+    }
+
+    // Whether the cover pages are stamped onto the original's pages rather
+    // than inserted
+    pub fn is_stamp(self) -> bool {
+        matches!(self, Position::Overlay | Position::Underlay)
+    }
+
+    // Number of original pages dropped for a document with `page_count` pages
+    pub fn pages_replaced(self, page_count: usize) -> usize {
+        match self {
+            Position::Replace => page_count.min(1),
+            _ => 0,
+        }
+    }
+}
+
+// Range of original pages `first..=last` in qpdf page-range syntax
+fn page_range(first: usize, last: usize) -> String {
+    if first == last {
+        first.to_string()
+    } else {
+        format!("{}-{}", first, last)
+    }
+}
+
+// qpdf --pages arguments placing the `inserted` (file, page range) pairs, in
+// order, into `target` (which has `page_count` pages) at `position`, leaving
+// out the target's `removed` pages
+pub fn insert_page_spec(
+    inserted: &[(&str, &str)],
+    target: &str,
+    page_count: usize,
+    position: Position,
+    removed: &[u32],
+) -> Vec<String> {
+    let before = position.pages_before(page_count);
+    let after = before + position.pages_replaced(page_count);
+    let mut spec = Vec::new();
+    let push_target = |first: usize, last: usize, spec: &mut Vec<String>| {
+        let kept: Vec<u32> = (first as u32..=last as u32)
+            .filter(|page| !removed.contains(page))
+            .collect();
+        if !kept.is_empty() {
+            spec.push(target.to_string());
+            spec.push(format_pages(&kept));
+        }
+    };
+
+    push_target(1, before, &mut spec);
+    for (file, pages) in inserted {
+        spec.push(file.to_string());
+        spec.push(pages.to_string());
+    }
+    push_target(after + 1, page_count, &mut spec);
+
+    spec
+}
+
+// qpdf page range of `target` (with `page_count` pages, `inserted` of them
+// added at `position`) that leaves out the inserted pages
+pub fn remove_page_range(page_count: usize, inserted: usize, position: Position) -> String {
+    let original = page_count - inserted;
+    let before = position.pages_before(original);
+
+    let mut ranges = Vec::new();
+    if before > 0 {
+        ranges.push(page_range(1, before));
+    }
+    if before < original {
+        ranges.push(page_range(before + inserted + 1, page_count));
+    }
+    ranges.join(",")
+}
@@ -0,0 +1,1070 @@
+use crate::attachments::{self, Attachments};
+use crate::blank::{self, BlankPage};
+use crate::cleanup::Leftover;
+use crate::footer::{self, Align};
+use crate::grayscale::{self, Grayscale};
+use crate::image_cover;
+use crate::marker::Marker;
+use crate::pages::format_pages;
+use crate::position::{self, Position};
+use crate::{
+    backup, bates, forms, marker, metadata, outline, pdfa, scan, signatures, template, watermark,
+    Job, SignedPolicy,
+};
+use log::{info, warn};
+use lopdf::Document;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// qpdf invocation that survives Ctrl+C in the terminal: on Unix it runs in
+// its own process group, so the interrupt only reaches this process, which
+// lets the current merge finish before stopping
+fn qpdf_command() -> Command {
+    let mut command = Command::new("qpdf");
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+    }
+    command
+}
+
+// A file only the current user can read, holding what qpdf must not get on
+// its command line, where any local user could read it in the process list;
+// removed when dropped
+struct SecretFile {
+    path: PathBuf,
+    _leftover: Leftover,
+}
+
+impl SecretFile {
+    fn new(contents: &str) -> std::io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "insert-bia-{}-{}.tmp",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&path)?;
+        let secret = SecretFile {
+            _leftover: Leftover::file(&path),
+            path,
+        };
+        file.write_all(contents.as_bytes())?;
+        Ok(secret)
+    }
+
+    // `option` naming the file, as in --password-file=<file>
+    fn option(&self, option: &str) -> OsString {
+        let mut arg = OsString::from(option);
+        arg.push(&self.path);
+        arg
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Give qpdf `password` to open its input with, through a secret file kept
+// until the command has run
+fn add_password(
+    command: &mut Command,
+    password: Option<&str>,
+) -> std::io::Result<Option<SecretFile>> {
+    let Some(password) = password else {
+        return Ok(None);
+    };
+    let secret = SecretFile::new(password)?;
+    command.arg(secret.option("--password-file="));
+    Ok(Some(secret))
+}
+
+pub fn check_qpdf_installed() -> bool {
+    match qpdf_command().arg("--version").output() {
+        Ok(output) => output.status.success(),
+        Err(_) => false,
+    }
+}
+
+pub fn get_pdf_page_count(pdf_path: &Path) -> Result<usize, Box<dyn std::error::Error>> {
+    count_pages(pdf_path, None)
+}
+
+// The page count of `pdf_path`, opened with `password` when it is encrypted
+fn count_pages(
+    pdf_path: &Path,
+    password: Option<&str>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    // Use qpdf to get page count
+    let mut command = qpdf_command();
+    let _secret = add_password(&mut command, password)?;
+    let output = command
+        .args(["--show-npages", pdf_path.to_str().unwrap()])
+        .output()?;
+
+    if !output.status.success() {
+        return Err(format!("qpdf failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let count = stdout.trim().parse::<usize>()?;
+
+    Ok(count)
+}
+
+#[derive(Debug)]
+pub struct InsertedPage {
+    // 1-based bia.pdf pages that were inserted, in order
+    pub pages: Vec<u32>,
+    // Excel key the file matched
+    pub key: String,
+    // Anything qpdf printed on stderr (warnings, given --warning-exit-0)
+    pub qpdf_stderr: String,
+    // Copy of the original, when a backup directory is configured
+    pub backup: Option<PathBuf>,
+    // Where the file was renamed to, with an output name in the mapping
+    pub renamed: Option<PathBuf>,
+    // Bates numbers of its first and last pages, when they were numbered
+    pub bates: Option<(u64, u64)>,
+    // Why a PDF/A file's output no longer conforms, when it does not
+    pub pdfa_lost: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ProcessOutcome {
+    Inserted(InsertedPage),
+    // The file carries the marker of an earlier run and was left untouched
+    AlreadyProcessed { key: String, pages: Vec<u32> },
+    // The file has no entry in the mapping
+    Unmatched,
+    // The mapping excludes the file
+    Excluded { key: String },
+    // The file is digitally signed and the job leaves such files alone
+    Signed { key: String },
+    // Online-only cloud file that was not downloaded
+    Placeholder,
+}
+
+// The file that is actually rewritten: for a symbolic link (followed while
+// scanning) the file it points to, so the link itself is kept
+fn replaced_file(pdf_path: &Path) -> PathBuf {
+    match fs::symlink_metadata(pdf_path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            fs::canonicalize(pdf_path).unwrap_or_else(|_| pdf_path.to_path_buf())
+        }
+        _ => pdf_path.to_path_buf(),
+    }
+}
+
+// Temp file in the target's own directory; the extension keeps it out of scans
+pub(crate) fn temp_output_path(pdf_path: &Path) -> PathBuf {
+    let filename = pdf_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("output");
+    pdf_path.with_file_name(format!(
+        ".{}.insert-bia-{}.tmp",
+        filename,
+        std::process::id()
+    ))
+}
+
+// Check that the output in `temp_output_pdf` (opened with `password`) has
+// exactly `expected` pages, deleting it otherwise so a truncated or empty
+// merge never replaces anything
+fn verify_page_count(
+    temp_output_pdf: &Path,
+    expected: usize,
+    password: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let actual = match count_pages(temp_output_pdf, password) {
+        Ok(actual) => actual,
+        Err(e) => {
+            let _ = fs::remove_file(temp_output_pdf);
+            return Err(format!("Failed to verify output: {}", e).into());
+        }
+    };
+
+    if actual != expected {
+        let _ = fs::remove_file(temp_output_pdf);
+        return Err(format!(
+            "Output has {} pages, expected {}; original left unchanged",
+            actual, expected
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+// Atomically move `temp_output_pdf` over `pdf_path`, keeping the permissions
+// of the file it replaces
+pub(crate) fn replace_with_temp(
+    temp_output_pdf: &Path,
+    pdf_path: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if let Ok(metadata) = fs::metadata(pdf_path) {
+        let _ = fs::set_permissions(temp_output_pdf, metadata.permissions());
+    }
+    if let Err(e) = fs::rename(temp_output_pdf, pdf_path) {
+        let _ = fs::remove_file(temp_output_pdf);
+        return Err(format!("Failed to replace original: {}", e).into());
+    }
+    Ok(())
+}
+
+// `next_bates` is the Bates number of the file's first page, when the job
+// numbers them
+pub fn process_pdf_with_qpdf(
+    pdf_path: &Path,
+    job: &Job,
+    next_bates: u64,
+) -> Result<ProcessOutcome, Box<dyn std::error::Error>> {
+    // Match PDF with Excel entries (or the manifest) and get the used key
+    let insertion = match job.lookup(pdf_path) {
+        Some(insertion) => insertion,
+        None => return Ok(ProcessOutcome::Unmatched), // No match, skip
+    };
+    if insertion.excluded {
+        return Ok(ProcessOutcome::Excluded { key: insertion.key });
+    }
+    let used_key = insertion.key.clone();
+    let position = insertion.position;
+    let (bia_path, bia_page_count) = job.cover_of(&insertion);
+    let bia_name = bia_path.file_name().unwrap_or_default().to_string_lossy();
+
+    // Checked before anything reads the file, which would start a download
+    if scan::is_cloud_placeholder(pdf_path) {
+        if !job.config.hydrate_placeholders {
+            return Ok(ProcessOutcome::Placeholder);
+        }
+        info!("Downloading online-only file {}", pdf_path.display());
+        scan::hydrate(pdf_path).map_err(|e| format!("Failed to download file: {}", e))?;
+    }
+
+    // Encrypted files are opened with the first password given that works
+    let candidates: Vec<&str> = insertion
+        .password
+        .iter()
+        .chain(&job.config.passwords)
+        .map(String::as_str)
+        .collect();
+    let password = find_password(pdf_path, &candidates)?;
+
+    // Never insert a second cover into a file processed by an earlier run
+    if let Some(marker) = marker::read_marker_with_password(pdf_path, password) {
+        return Ok(ProcessOutcome::AlreadyProcessed {
+            key: used_key,
+            pages: marker.pages,
+        });
+    }
+
+    // Parsed once, to leave signed files alone and for what the merge must
+    // keep below
+    let target = match password {
+        Some(password) => Document::load_with_password(pdf_path, password),
+        None => Document::load(pdf_path),
+    }
+    .ok();
+    if target.as_ref().is_some_and(signatures::is_signed) {
+        match job.config.signed {
+            SignedPolicy::Skip => return Ok(ProcessOutcome::Signed { key: used_key }),
+            SignedPolicy::Warn => warn!(
+                "{} is digitally signed; its signatures will no longer be valid",
+                pdf_path.display()
+            ),
+            SignedPolicy::Force => {}
+        }
+    }
+
+    let pages = insertion.pages.clone();
+    let page_list = format_pages(&pages);
+
+    // Checked up front, so a taken name never leaves a processed file behind
+    // under its old name
+    let renamed = insertion
+        .output_name
+        .as_ref()
+        .map(|name| pdf_path.with_file_name(name))
+        .filter(|renamed| renamed != pdf_path);
+    if let Some(renamed) = &renamed {
+        if renamed.exists() {
+            return Err(format!("Output file already exists: {}", renamed.display()).into());
+        }
+    }
+
+    // Validate page numbers
+    if let Some(&page_number) = pages.iter().find(|&&p| p as usize > bia_page_count) {
+        return Err(format!(
+            "Page number {} exceeds {} page count ({})",
+            page_number, bia_name, bia_page_count
+        )
+        .into());
+    }
+
+    // Needed to verify the merged output before it replaces the original
+    let original_page_count = count_pages(pdf_path, password)
+        .map_err(|e| format!("Failed to get page count of original: {}", e))?;
+
+    let mut removed = insertion.remove_pages.clone();
+    removed.sort();
+    removed.dedup();
+    if let Some(&page_number) = removed.iter().find(|&&p| p as usize > original_page_count) {
+        return Err(format!(
+            "Page {} to remove exceeds the file's page count ({})",
+            page_number, original_page_count
+        )
+        .into());
+    }
+    let kept_page_count =
+        original_page_count - removed.len() - position.pages_replaced(original_page_count);
+    // Files that only lose pages get no blank page, unless it replaces the cover
+    let blank = job
+        .config
+        .blank_page
+        .filter(|&blank| blank == BlankPage::Instead || !pages.is_empty());
+    // Stamped cover pages go onto the first original pages, one each
+    let stamped = position.is_stamp() && !pages.is_empty();
+    if stamped && kept_page_count < pages.len() {
+        return Err(format!(
+            "Cannot stamp {} pages onto a file with {} pages",
+            pages.len(),
+            kept_page_count
+        )
+        .into());
+    }
+    let inserted_page_count = if stamped { 0 } else { pages.len() } + usize::from(blank.is_some());
+    if kept_page_count + inserted_page_count == 0 {
+        return Err("Removing the pages would leave the file empty".into());
+    }
+
+    if stamped {
+        info!(
+            "Stamping page {} from {} onto {} ({})",
+            page_list,
+            bia_name,
+            pdf_path.display(),
+            position
+        );
+    } else if inserted_page_count == 0 {
+        info!(
+            "Removing page {} from {}",
+            format_pages(&removed),
+            pdf_path.display()
+        );
+    } else {
+        info!(
+            "Inserting page {} from {} into {} ({})",
+            page_list,
+            bia_name,
+            pdf_path.display(),
+            position
+        );
+    }
+
+    // Write the merged output next to the target so the final rename stays on
+    // the same volume and is atomic (a crash never leaves a truncated target)
+    let replaced = replaced_file(pdf_path);
+    let temp_output_pdf = temp_output_path(&replaced);
+    let _leftover = Leftover::temp_output(&temp_output_pdf);
+
+    // The blank page is generated into a file of its own for qpdf to take it from
+    let blank_pdf = temp_output_pdf.with_extension("blank.tmp");
+    if blank.is_some() {
+        blank::write_blank_page(pdf_path, &blank_pdf)
+            .map_err(|e| format!("Failed to create blank page: {}", e))?;
+    }
+    // So is a cover page from the template, or from a cover image fitted to
+    // the page
+    let generated_pdf = temp_output_pdf.with_extension("cover.tmp");
+    let generated = if pages.is_empty() {
+        None
+    } else if let Some(template) = &job.template {
+        let filename = pdf_path.file_name().unwrap_or_default().to_string_lossy();
+        Some(
+            template
+                .render(&insertion.fields, &filename, &used_key, &generated_pdf)
+                .map_err(|e| format!("Failed to fill in the cover template: {}", e)),
+        )
+    } else if image_cover::is_image(bia_path) {
+        Some(
+            image_cover::write_image_page(bia_path, job.config.image_fit, pdf_path, &generated_pdf)
+                .map_err(|e| format!("Failed to convert {}: {}", bia_name, e)),
+        )
+    } else {
+        None
+    };
+    if let Some(Err(e)) = generated {
+        let _ = fs::remove_file(&blank_pdf);
+        let _ = fs::remove_file(&generated_pdf);
+        return Err(e.into());
+    }
+    // And the watermark, filled in for this file
+    let watermark_pdf = temp_output_pdf.with_extension("watermark.tmp");
+    let watermark = match &job.config.watermark {
+        Some(text) => {
+            let filename = pdf_path.file_name().unwrap_or_default().to_string_lossy();
+            let written = template::fill_in(text, &insertion.fields, &filename, &used_key)
+                .map_err(|e| e.into())
+                .and_then(|text| {
+                    watermark::write_watermark_page(&text, pdf_path, &watermark_pdf).map(|_| text)
+                });
+            match written {
+                Ok(text) => Some(text),
+                Err(e) => {
+                    let _ = fs::remove_file(&blank_pdf);
+                    let _ = fs::remove_file(&generated_pdf);
+                    let _ = fs::remove_file(&watermark_pdf);
+                    return Err(format!("Failed to create watermark: {}", e).into());
+                }
+            }
+        }
+        None => None,
+    };
+    let cover_pdf = if generated.is_some() {
+        &generated_pdf
+    } else {
+        bia_path
+    };
+    // A gray copy of the cover, its other pages left as they are
+    let gray_pdf = temp_output_pdf.with_extension("gray.tmp");
+    let gray = job.config.grayscale == Some(Grayscale::Cover) && !pages.is_empty();
+    if gray {
+        let converted = grayscale::convert_pages(cover_pdf, &gray_pdf, |index| {
+            pages.contains(&(index as u32 + 1))
+        });
+        if let Err(e) = converted {
+            let _ = fs::remove_file(&blank_pdf);
+            let _ = fs::remove_file(&generated_pdf);
+            let _ = fs::remove_file(&watermark_pdf);
+            let _ = fs::remove_file(&gray_pdf);
+            return Err(format!("Failed to convert the cover to grayscale: {}", e).into());
+        }
+    }
+    let cover_pdf = if gray { &gray_pdf } else { cover_pdf };
+    let mut inserted = Vec::new();
+    if matches!(blank, Some(BlankPage::Before | BlankPage::Instead)) {
+        inserted.push((blank_pdf.to_str().unwrap(), "1"));
+    }
+    if !pages.is_empty() && !stamped {
+        inserted.push((cover_pdf.to_str().unwrap(), page_list.as_str()));
+    }
+    if blank == Some(BlankPage::After) {
+        inserted.push((blank_pdf.to_str().unwrap(), "1"));
+    }
+
+    // Annotations and form fields the merged file should keep: those on the
+    // target's remaining pages and on inserted cover pages (stamped ones are
+    // drawn without theirs). Not checked when either cannot be parsed, nor
+    // when flattening draws them into the pages.
+    let replaced_page = position.pages_before(original_page_count) as u32 + 1;
+    let kept_pages: Vec<u32> = (1..=original_page_count as u32)
+        .filter(|page| !removed.contains(page))
+        .filter(|&page| position.pages_replaced(original_page_count) == 0 || page != replaced_page)
+        .collect();
+    let count_expected = |target: &Document| {
+        let kept = forms::count_annotations(target, &kept_pages);
+        if pages.is_empty() || stamped || generated.is_some() {
+            return Some(kept);
+        }
+        let cover = Document::load(bia_path).ok()?;
+        Some(kept + forms::count_annotations(&cover, &pages))
+    };
+    let expected_annotations = match &target {
+        Some(target) if !job.config.flatten_forms => count_expected(target),
+        _ => None,
+    };
+    // And its embedded files
+    let expected_attachments = target
+        .as_ref()
+        .map(Attachments::of)
+        .filter(|attachments| !attachments.is_empty());
+    let original_metadata = target
+        .as_ref()
+        .map(metadata::read_document_metadata)
+        .unwrap_or_default();
+    // And whether it is a PDF/A file, whose output is checked for conformance
+    let conformance = target.as_ref().and_then(pdfa::claimed);
+    // Not kept in memory while qpdf runs
+    drop(target);
+
+    // Use qpdf to combine the pages from bia.pdf with the pages of the target,
+    // in the order given by the position (front: bia.pdf N[,M...] target.pdf)
+    // qpdf target.pdf --pages <spec> -- output.pdf
+    // The target is the primary input ("." in the spec) so its outline, named
+    // destinations, links, form fields and embedded files are kept: they
+    // point at its page objects, which qpdf keeps too, so they still land on
+    // the same pages.
+    // --decrypt writes the output unencrypted, as a new document would be;
+    // it is encrypted again, if at all, after the changes made below.
+    // Flattening form fields first gives them appearances that show their
+    // values.
+    // Use --warning-exit-0 to return success even with warnings (common in non-standard PDFs)
+    let page_spec =
+        position::insert_page_spec(&inserted, ".", original_page_count, position, &removed);
+    let mut command = qpdf_command();
+    command.args(["--warning-exit-0", "--decrypt"]);
+    let secret = match add_password(&mut command, password) {
+        Ok(secret) => secret,
+        Err(e) => {
+            let _ = fs::remove_file(&blank_pdf);
+            let _ = fs::remove_file(&generated_pdf);
+            let _ = fs::remove_file(&watermark_pdf);
+            let _ = fs::remove_file(&gray_pdf);
+            return Err(format!("Failed to pass the password to qpdf: {}", e).into());
+        }
+    };
+    command
+        .arg(pdf_path)
+        .arg("--pages")
+        .args(&page_spec)
+        .arg("--");
+    if job.config.flatten_forms {
+        command.args(["--generate-appearances", "--flatten-annotations=all"]);
+    }
+    if job.config.deterministic {
+        command.arg("--deterministic-id");
+    }
+    // qpdf --overlay bia.pdf --from=N --to=1 -- stamps onto the output's pages,
+    // which start after a blank page put in front
+    if stamped {
+        let first = 1 + usize::from(blank.is_some()) as u32;
+        let onto: Vec<u32> = (first..first + pages.len() as u32).collect();
+        command
+            .arg(if position == Position::Overlay {
+                "--overlay"
+            } else {
+                "--underlay"
+            })
+            .arg(cover_pdf)
+            .arg(format!("--from={}", page_list))
+            .arg(format!("--to={}", format_pages(&onto)))
+            .arg("--");
+    }
+    // The watermark's one page repeated onto every page of the output
+    if watermark.is_some() {
+        command.arg("--overlay").arg(&watermark_pdf).args([
+            "--to=1-z",
+            "--from=",
+            "--repeat=1",
+            "--",
+        ]);
+    }
+    let output = command.arg(&temp_output_pdf).output();
+    drop(secret);
+    if watermark.is_some() {
+        let _ = fs::remove_file(&watermark_pdf);
+    }
+    if blank.is_some() {
+        let _ = fs::remove_file(&blank_pdf);
+    }
+    if generated.is_some() {
+        let _ = fs::remove_file(&generated_pdf);
+    }
+    if gray {
+        let _ = fs::remove_file(&gray_pdf);
+    }
+
+    let output = match output {
+        Ok(output) if output.status.success() => output,
+        Ok(output) => {
+            let _ = fs::remove_file(&temp_output_pdf);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Failed to merge PDFs with qpdf: {}", stderr).into());
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(e.into());
+        }
+    };
+
+    // Verify output exists
+    if !temp_output_pdf.exists() {
+        return Err("Failed to create merged PDF".into());
+    }
+
+    if expected_annotations.is_some() || expected_attachments.is_some() {
+        let checked = Document::load(&temp_output_pdf)
+            .map_err(|e| e.to_string())
+            .and_then(|merged| {
+                if let Some(expected) = expected_annotations {
+                    forms::check_merged(&merged, expected)?;
+                }
+                if let Some(expected) = &expected_attachments {
+                    attachments::check_merged(&merged, expected)?;
+                }
+                Ok(())
+            });
+        if let Err(e) = checked {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Merged PDF failed the check: {}", e).into());
+        }
+    }
+
+    if job.config.grayscale == Some(Grayscale::All) {
+        if let Err(e) = grayscale::convert_pages(&temp_output_pdf, &temp_output_pdf, |_| true) {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to convert to grayscale: {}", e).into());
+        }
+    }
+
+    if let Err(e) = metadata::restore_document_metadata(&temp_output_pdf, &original_metadata) {
+        let _ = fs::remove_file(&temp_output_pdf);
+        return Err(format!("Failed to carry over document metadata: {}", e).into());
+    }
+    if let Err(e) = metadata::set_document_info(&temp_output_pdf, &insertion.info) {
+        let _ = fs::remove_file(&temp_output_pdf);
+        return Err(format!("Failed to set document info: {}", e).into());
+    }
+
+    // The original pages numbered among themselves, around the inserted ones
+    if let Some(format) = &job.config.page_numbers {
+        let before = position.kept_pages_before(original_page_count, &removed);
+        let inserted = before..before + inserted_page_count;
+        let stamped = footer::stamp_footers(&temp_output_pdf, Align::Center, |index| {
+            let page = match index {
+                index if inserted.contains(&index) => return None,
+                index if index < before => index + 1,
+                index => index + 1 - inserted_page_count,
+            };
+            Some(page_number(format, page, kept_page_count))
+        });
+        if let Err(e) = stamped {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to stamp page numbers: {}", e).into());
+        }
+    }
+
+    // The bookmark goes to the first cover page, past a blank page before it
+    let bookmark = job.config.bookmark.clone().filter(|_| !pages.is_empty());
+    if let Some(title) = &bookmark {
+        let cover_index = if stamped {
+            0
+        } else {
+            position.kept_pages_before(original_page_count, &removed)
+        } + usize::from(blank == Some(BlankPage::Before));
+        if let Err(e) = outline::add_bookmark(&temp_output_pdf, title, cover_index) {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to add bookmark: {}", e).into());
+        }
+    }
+
+    let bates = match &job.config.bates {
+        Some(bates) => match bates::stamp_numbers(&temp_output_pdf, bates, next_bates) {
+            Ok(last) => Some((next_bates, last)),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_output_pdf);
+                return Err(format!("Failed to stamp Bates numbers: {}", e).into());
+            }
+        },
+        None => None,
+    };
+
+    let marker = Marker {
+        pages: pages.clone(),
+        position,
+        removed,
+        blank,
+        watermark,
+        bates: bates
+            .zip(job.config.bates.as_ref())
+            .map(|((first, last), numbering)| numbering.range(first, last)),
+        page_numbers: job.config.page_numbers.is_some(),
+        bookmark,
+        flattened: job.config.flatten_forms,
+    };
+    if let Err(e) = marker::write_marker(&temp_output_pdf, &marker) {
+        let _ = fs::remove_file(&temp_output_pdf);
+        return Err(format!("Failed to mark merged PDF: {}", e).into());
+    }
+
+    // The row's password, else the one the file was opened with when it is
+    // kept, else the job's; the owner password defaults to it
+    let open_password = insertion
+        .encrypt_password
+        .as_deref()
+        .or(password.filter(|_| job.config.keep_password))
+        .or(job.config.encrypt_password.as_deref());
+    let encryption = match (open_password, job.config.owner_password.as_deref()) {
+        (None, None) => None,
+        (open, owner) => {
+            let open = open.unwrap_or_default();
+            Some((open, owner.unwrap_or(open)))
+        }
+    };
+
+    // Checked before encryption, which makes the file unreadable to lopdf
+    let pdfa_lost = conformance.and_then(|conformance| {
+        let mut problems = Document::load(&temp_output_pdf)
+            .map(|output| pdfa::problems(&output, &conformance))
+            .unwrap_or_default();
+        if encryption.is_some() {
+            problems.push("it is encrypted".to_string());
+        }
+        if problems.is_empty() {
+            return None;
+        }
+        Some(format!("{}: {}", conformance, problems.join("; ")))
+    });
+
+    // Last, since rewriting the file with lopdf above would undo it
+    let options = output_options(job, encryption);
+    if !options.is_empty() {
+        if let Err(e) = rewrite_output(&temp_output_pdf, &options) {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to write the output: {}", e).into());
+        }
+    }
+
+    verify_page_count(
+        &temp_output_pdf,
+        kept_page_count + inserted_page_count,
+        open_password.filter(|password| !password.is_empty()),
+    )?;
+
+    // Keep the original before it is overwritten
+    let backup = match &job.config.backup_dir {
+        Some(backup_dir) => match backup::backup_original(pdf_path, &job.config.dirs, backup_dir) {
+            Ok(backup) => Some(backup),
+            Err(e) => {
+                let _ = fs::remove_file(&temp_output_pdf);
+                return Err(format!("Failed to back up original: {}", e).into());
+            }
+        },
+        None => None,
+    };
+
+    // Replace original file with merged output, keeping its permissions
+    replace_with_temp(&temp_output_pdf, &replaced)?;
+
+    if let Some(renamed) = &renamed {
+        fs::rename(pdf_path, renamed).map_err(|e| {
+            format!(
+                "Pages were inserted, but renaming to {} failed: {}",
+                renamed.display(),
+                e
+            )
+        })?;
+        // So a rollback removes the file under its new name
+        if let (Some(backup_dir), Some(backup)) = (&job.config.backup_dir, &backup) {
+            if let Err(e) = backup::record_rename(backup_dir, backup, renamed) {
+                warn!(
+                    "Failed to record the rename of {} for rollback: {}",
+                    pdf_path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(ProcessOutcome::Inserted(InsertedPage {
+        pages,
+        key: used_key,
+        qpdf_stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        backup,
+        renamed,
+        bates,
+        pdfa_lost,
+    }))
+}
+
+// The first of `candidates` that opens `pdf_path`, None when it opens
+// without a password
+fn find_password<'a>(
+    pdf_path: &Path,
+    candidates: &[&'a str],
+) -> Result<Option<&'a str>, Box<dyn std::error::Error>> {
+    // qpdf --requires-password exits with 0 when the file still needs a
+    // password, 2 when it is not encrypted and 3 when it opens
+    let opens = |password: Option<&str>| -> Result<bool, Box<dyn std::error::Error>> {
+        let mut command = qpdf_command();
+        let _secret = add_password(&mut command, password)?;
+        let output = command.arg("--requires-password").arg(pdf_path).output()?;
+        Ok(output.status.code() != Some(0))
+    };
+
+    if opens(None)? {
+        return Ok(None);
+    }
+    for &candidate in candidates {
+        if opens(Some(candidate))? {
+            return Ok(Some(candidate));
+        }
+    }
+    Err(match candidates.len() {
+        0 => "The file is password-protected; give its password with --password, --password-col or --password-file".into(),
+        1 => "The file is password-protected and the password given does not open it".into(),
+        count => format!(
+            "The file is password-protected and none of the {} passwords given opens it",
+            count
+        )
+        .into(),
+    })
+}
+
+// qpdf options for how the processed file is written, encrypted with the
+// open and owner passwords of `encryption`
+fn output_options(job: &Job, encryption: Option<(&str, &str)>) -> Vec<String> {
+    let mut options = Vec::new();
+    if job.config.linearize {
+        options.push("--linearize".to_string());
+    }
+    if job.config.optimize {
+        // qpdf leaves out objects nothing refers to anyway
+        options.extend(
+            [
+                "--object-streams=generate",
+                "--recompress-flate",
+                "--compression-level=9",
+                "--remove-unreferenced-resources=yes",
+            ]
+            .map(String::from),
+        );
+    }
+    if job.config.optimize_images {
+        options.push("--optimize-images".to_string());
+    }
+    // qpdf leaves out features the forced version lacks where it can, such
+    // as object streams below 1.5
+    if let Some(version) = &job.config.pdf_version {
+        options.push(format!("--force-version={}", version));
+    }
+    if let Some(version) = &job.config.min_pdf_version {
+        options.push(format!("--min-version={}", version));
+    }
+    // Recomputed for the contents written since the merge
+    if job.config.deterministic {
+        options.push("--deterministic-id".to_string());
+    }
+    if let Some((open, owner)) = encryption {
+        options.extend(["--encrypt", open, owner, "256", "--"].map(String::from));
+    }
+    options
+}
+
+// qpdf argument file (@file) with `options`, one per line, since they may
+// hold the encryption passwords
+fn argument_file(options: &[String]) -> Result<SecretFile, Box<dyn std::error::Error>> {
+    if options.iter().any(|option| option.contains(['\n', '\r'])) {
+        return Err("passwords cannot contain line breaks".into());
+    }
+    let mut lines = options.join("\n");
+    lines.push('\n');
+    Ok(SecretFile::new(&lines)?)
+}
+
+// Rewrite `temp_output_pdf` through qpdf with `options`
+fn rewrite_output(
+    temp_output_pdf: &Path,
+    options: &[String],
+) -> Result<(), Box<dyn std::error::Error>> {
+    let rewritten = temp_output_pdf.with_extension("rewrite.tmp");
+    let arguments = argument_file(options)?;
+    let output = qpdf_command()
+        .arg("--warning-exit-0")
+        .arg(arguments.option("@"))
+        .arg(temp_output_pdf)
+        .arg(&rewritten)
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            let _ = fs::remove_file(&rewritten);
+            return Err(String::from_utf8_lossy(&output.stderr)
+                .trim()
+                .to_string()
+                .into());
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&rewritten);
+            return Err(e.into());
+        }
+    }
+    fs::rename(&rewritten, temp_output_pdf)?;
+    Ok(())
+}
+
+// `format` with {page} and {pages} filled in
+fn page_number(format: &str, page: usize, pages: usize) -> String {
+    format
+        .replace("{page}", &page.to_string())
+        .replace("{pages}", &pages.to_string())
+}
+
+// The first of `passwords` that opens `pdf_path`, None when it is not
+// encrypted; an error when none of them opens it
+pub fn opening_password<'a>(
+    pdf_path: &Path,
+    passwords: &'a [String],
+) -> Result<Option<&'a str>, Box<dyn std::error::Error>> {
+    let candidates: Vec<&str> = passwords.iter().map(String::as_str).collect();
+    find_password(pdf_path, &candidates)
+}
+
+// Strip the cover pages inserted by an earlier run from a marked file,
+// restoring its original pages; an encrypted file is opened with `password`
+// and encrypted with it again. Returns the recorded insertion, or None when
+// the file carries no marker.
+pub fn remove_inserted_page(
+    pdf_path: &Path,
+    password: Option<&str>,
+) -> Result<Option<Marker>, Box<dyn std::error::Error>> {
+    let marker = match marker::read_marker_with_password(pdf_path, password) {
+        Some(marker) => marker,
+        None => return Ok(None),
+    };
+
+    // Replaced and removed pages are not kept in the file
+    if marker.position == Position::Replace {
+        return Err("Its first page was replaced; use rollback to restore it from a backup".into());
+    }
+    if marker.position.is_stamp() {
+        return Err(
+            "The cover was stamped onto its pages; use rollback to restore it from a backup".into(),
+        );
+    }
+    if !marker.removed.is_empty() {
+        return Err("Pages were removed from it; use rollback to restore it from a backup".into());
+    }
+    if marker.watermark.is_some()
+        || marker.bates.is_some()
+        || marker.page_numbers
+        || marker.flattened
+    {
+        return Err(
+            "Its pages were watermarked, numbered or flattened; use rollback to restore it from a backup"
+                .into(),
+        );
+    }
+
+    let inserted = marker.pages.len() + usize::from(marker.blank.is_some());
+    let original_page_count =
+        count_pages(pdf_path, password).map_err(|e| format!("Failed to get page count: {}", e))?;
+    if original_page_count <= inserted {
+        return Err("File has no pages besides the inserted ones".into());
+    }
+
+    info!("Removing inserted pages from {}", pdf_path.display());
+
+    let replaced = replaced_file(pdf_path);
+    let temp_output_pdf = temp_output_path(&replaced);
+    let _leftover = Leftover::temp_output(&temp_output_pdf);
+
+    // Keep every page except those inserted at the recorded position
+    let kept_pages = position::remove_page_range(original_page_count, inserted, marker.position);
+    // The file is the primary input, keeping its outline and links
+    let mut command = qpdf_command();
+    let _secret = add_password(&mut command, password)?;
+    let output = command
+        .args(["--warning-exit-0", "--decrypt"])
+        .arg(pdf_path)
+        .args(["--pages", ".", &kept_pages, "--"])
+        .arg(&temp_output_pdf)
+        .output();
+
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            let _ = fs::remove_file(&temp_output_pdf);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("Failed to remove page with qpdf: {}", stderr).into());
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(e.into());
+        }
+    }
+
+    // The info dictionary is carried over with the marker, and the outline
+    // with the bookmark for the cover, now going to a removed page
+    if let Err(e) = marker::clear_marker(&temp_output_pdf) {
+        let _ = fs::remove_file(&temp_output_pdf);
+        return Err(format!("Failed to clear marker: {}", e).into());
+    }
+    if let Some(title) = &marker.bookmark {
+        if let Err(e) = outline::remove_bookmark(&temp_output_pdf, title) {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to remove bookmark: {}", e).into());
+        }
+    }
+
+    // Decrypted above for lopdf; the owner password, if it was another one,
+    // is not known and becomes the same
+    if let Some(password) = password {
+        let options = ["--encrypt", password, password, "256", "--"].map(String::from);
+        if let Err(e) = rewrite_output(&temp_output_pdf, &options) {
+            let _ = fs::remove_file(&temp_output_pdf);
+            return Err(format!("Failed to encrypt the output: {}", e).into());
+        }
+    }
+
+    verify_page_count(
+        &temp_output_pdf,
+        original_page_count - inserted,
+        password.filter(|password| !password.is_empty()),
+    )?;
+
+    replace_with_temp(&temp_output_pdf, &replaced)?;
+
+    Ok(Some(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secret_file_is_private_and_removed() {
+        let secret = SecretFile::new("hunter2").unwrap();
+        let path = secret.path.clone();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "hunter2");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        drop(secret);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn password_is_not_on_the_command_line() {
+        let mut command = qpdf_command();
+        let secret = add_password(&mut command, Some("hunter2"))
+            .unwrap()
+            .unwrap();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, [secret.option("--password-file=")]);
+        assert!(!args[0].to_string_lossy().contains("hunter2"));
+    }
+
+    #[test]
+    fn encryption_passwords_go_in_the_argument_file() {
+        let options: Vec<String> = ["--linearize", "--encrypt", "", "owner", "256", "--"]
+            .map(String::from)
+            .to_vec();
+        let arguments = argument_file(&options).unwrap();
+        assert_eq!(
+            fs::read_to_string(&arguments.path).unwrap(),
+            "--linearize\n--encrypt\n\nowner\n256\n--\n"
+        );
+
+        let split = ["--encrypt", "two\nlines", "owner", "256", "--"].map(String::from);
+        assert!(argument_file(&split).is_err());
+    }
+}
@@ -0,0 +1,387 @@
+use crate::last_run;
+use glob::{MatchOptions, Pattern};
+use log::{debug, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// What to do with symbolic links (and junctions on Windows) met while scanning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPolicy {
+    // Treat links like the file or directory they point to; a directory
+    // reached a second time through a link is not searched again
+    Follow,
+    // Ignore links altogether
+    Skip,
+}
+
+// Which files under the base directory are picked up
+#[derive(Debug, Clone)]
+pub struct ScanOptions {
+    // How many directory levels below the base directory are searched; files
+    // directly in the base directory are never included. None means no limit.
+    pub max_depth: Option<usize>,
+    // Only files whose name matches one of these are included; empty means all
+    pub include: Vec<Pattern>,
+    // Files whose name matches one of these are left out
+    pub exclude_files: Vec<Pattern>,
+    // Directories whose name matches one of these are not searched
+    pub exclude_dirs: Vec<Pattern>,
+    pub links: LinkPolicy,
+    // Only files modified at or after this time are included
+    pub modified_since: Option<SystemTime>,
+    // Only files modified since the last complete run over their base
+    // directory are included (all files if there was none)
+    pub since_last_run: bool,
+}
+
+// File names are matched case-insensitively, like the .pdf extension
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+impl ScanOptions {
+    fn is_included(&self, filename: &str) -> bool {
+        (self.include.is_empty() || matches_any(&self.include, filename))
+            && !matches_any(&self.exclude_files, filename)
+    }
+
+    fn is_dir_excluded(&self, dirname: &str) -> bool {
+        matches_any(&self.exclude_dirs, dirname)
+    }
+}
+
+fn matches_any(patterns: &[Pattern], name: &str) -> bool {
+    patterns
+        .iter()
+        .any(|pattern| pattern.matches_with(name, MATCH_OPTIONS))
+}
+
+impl Default for ScanOptions {
+    // Direct child directories only
+    fn default() -> Self {
+        ScanOptions {
+            max_depth: Some(1),
+            include: Vec::new(),
+            exclude_files: Vec::new(),
+            exclude_dirs: Vec::new(),
+            links: LinkPolicy::Follow,
+            modified_since: None,
+            since_last_run: false,
+        }
+    }
+}
+
+pub fn scan_child_directories(
+    base_dir: &Path,
+    options: &ScanOptions,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut pdf_files = Vec::new();
+
+    let mut modified_since = options.modified_since;
+    if options.since_last_run {
+        let last_run = last_run::read_last_run(base_dir)
+            .map_err(|e| format!("Failed to read time of last run: {}", e))?;
+        modified_since = modified_since.max(last_run);
+    }
+
+    // Directories still to read, with their depth below the base directory
+    let mut pending = vec![(base_dir.to_path_buf(), 0)];
+
+    // Canonical paths of the directories queued so far, so a link cycle
+    // cannot make the scan loop
+    let mut visited = HashSet::new();
+    visited.insert(fs::canonicalize(base_dir)?);
+
+    while let Some((dir, depth)) = pending.pop() {
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let path = entry.path();
+
+            if entry.file_type()?.is_symlink() {
+                if options.links == LinkPolicy::Skip {
+                    debug!("Skipping link {}", path.display());
+                    continue;
+                }
+                if !path.exists() {
+                    warn!("Skipping broken link {}", path.display());
+                    continue;
+                }
+            }
+
+            let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+
+            if path.is_dir() {
+                if options.max_depth.is_none_or(|max| depth < max) && !options.is_dir_excluded(name)
+                {
+                    if !visited.insert(fs::canonicalize(&path)?) {
+                        debug!("Already scanned {}, skipping", path.display());
+                        continue;
+                    }
+                    pending.push((path, depth + 1));
+                }
+            } else if depth > 0 && path.is_file() {
+                let is_pdf = path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"));
+                if is_pdf && options.is_included(name) && is_modified_since(&path, modified_since) {
+                    pdf_files.push(path);
+                }
+            }
+        }
+    }
+
+    Ok(pdf_files)
+}
+
+fn is_modified_since(path: &Path, since: Option<SystemTime>) -> bool {
+    let Some(since) = since else {
+        return true;
+    };
+    // Keep files whose time cannot be read rather than silently dropping them
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map_or(true, |modified| modified >= since)
+}
+
+// Scan several base directories into one list. A file reachable from more
+// than one base (nested bases) is listed once.
+pub fn scan_directories(
+    base_dirs: &[PathBuf],
+    options: &ScanOptions,
+) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+    let mut pdf_files = Vec::new();
+    for base_dir in base_dirs {
+        let files = scan_child_directories(base_dir, options)
+            .map_err(|e| format!("{}: {}", base_dir.display(), e))?;
+        pdf_files.extend(files);
+    }
+
+    if base_dirs.len() > 1 {
+        pdf_files.sort();
+        pdf_files.dedup();
+    }
+    Ok(pdf_files)
+}
+
+// The base directory `pdf_path` was found under; the innermost one when
+// bases are nested
+pub fn base_dir_of<'a>(pdf_path: &Path, base_dirs: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    base_dirs
+        .iter()
+        .filter(|base_dir| pdf_path.starts_with(base_dir))
+        .max_by_key(|base_dir| base_dir.components().count())
+}
+
+// Path of `pdf_path` relative to its base directory. With several bases it
+// is prefixed by the base directory's name so files from different bases
+// never collide.
+pub fn relative_path(pdf_path: &Path, base_dirs: &[PathBuf]) -> PathBuf {
+    let Some(base_dir) = base_dir_of(pdf_path, base_dirs) else {
+        return PathBuf::from(pdf_path.file_name().unwrap_or_default());
+    };
+
+    let relative = pdf_path.strip_prefix(base_dir).unwrap_or(pdf_path);
+    match (base_dirs.len() > 1, base_dir.file_name()) {
+        (true, Some(name)) => Path::new(name).join(relative),
+        _ => relative.to_path_buf(),
+    }
+}
+
+// Whether `path` is an online-only placeholder of a sync client (OneDrive,
+// SharePoint, ...) whose content has not been downloaded
+#[cfg(windows)]
+pub fn is_cloud_placeholder(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+
+    fs::metadata(path).is_ok_and(|metadata| {
+        metadata.file_attributes()
+            & (FILE_ATTRIBUTE_OFFLINE
+                | FILE_ATTRIBUTE_RECALL_ON_OPEN
+                | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+            != 0
+    })
+}
+
+// Whether `path` is an online-only placeholder: on Unix a non-empty file
+// that occupies no blocks on disk (stub left by a FUSE sync client)
+#[cfg(unix)]
+pub fn is_cloud_placeholder(path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0 && metadata.blocks() == 0)
+}
+
+#[cfg(not(any(windows, unix)))]
+pub fn is_cloud_placeholder(_path: &Path) -> bool {
+    false
+}
+
+// Read a placeholder through so the sync client downloads its content
+pub fn hydrate(path: &Path) -> std::io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    std::io::copy(&mut file, &mut std::io::sink())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use std::time::Duration;
+
+    // Empty files at `paths` under `dir`; scanning never reads them
+    fn touch(dir: &Path, paths: &[&str]) {
+        for path in paths {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+    }
+
+    // The files found under `dir`, relative to it and sorted
+    fn scan(dir: &Path, options: &ScanOptions) -> Vec<String> {
+        let mut found: Vec<String> = scan_child_directories(dir, options)
+            .unwrap()
+            .iter()
+            .map(|path| {
+                let relative = path.strip_prefix(dir).unwrap();
+                relative.to_string_lossy().replace('\\', "/")
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn patterns(patterns: &[&str]) -> Vec<Pattern> {
+        patterns.iter().map(|p| Pattern::new(p).unwrap()).collect()
+    }
+
+    #[test]
+    fn depth_limit() {
+        let dir = fixtures::scratch_dir("scan-depth");
+        touch(
+            &dir,
+            &[
+                "top.pdf",
+                "a/one.PDF",
+                "a/b/two.pdf",
+                "a/b/c/three.pdf",
+                "a/note.txt",
+            ],
+        );
+
+        assert_eq!(scan(&dir, &ScanOptions::default()), ["a/one.PDF"]);
+        let options = ScanOptions {
+            max_depth: Some(2),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(&dir, &options), ["a/b/two.pdf", "a/one.PDF"]);
+        let options = ScanOptions {
+            max_depth: None,
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            scan(&dir, &options),
+            ["a/b/c/three.pdf", "a/b/two.pdf", "a/one.PDF"]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_and_exclude_patterns() {
+        let dir = fixtures::scratch_dir("scan-patterns");
+        touch(
+            &dir,
+            &[
+                "a/HD001.pdf",
+                "a/hd002_signed.pdf",
+                "a/other.pdf",
+                "Old/HD003.pdf",
+            ],
+        );
+
+        let options = ScanOptions {
+            include: patterns(&["hd*"]),
+            exclude_files: patterns(&["*_SIGNED.pdf"]),
+            exclude_dirs: patterns(&["old"]),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(&dir, &options), ["a/HD001.pdf"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn modified_since() {
+        let dir = fixtures::scratch_dir("scan-modified");
+        touch(&dir, &["a/old.pdf", "a/new.pdf"]);
+        let old = SystemTime::now() - Duration::from_secs(3600);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("a/old.pdf"))
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+
+        let options = ScanOptions {
+            modified_since: Some(SystemTime::now() - Duration::from_secs(60)),
+            ..ScanOptions::default()
+        };
+        assert_eq!(scan(&dir, &options), ["a/new.pdf"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn links_and_cycles() {
+        use std::os::unix::fs::symlink;
+
+        let dir = fixtures::scratch_dir("scan-links");
+        let outside = fixtures::scratch_dir("scan-links-outside");
+        touch(&dir, &["a/real.pdf"]);
+        touch(&outside, &["linked.pdf"]);
+        symlink(&outside, dir.join("linked")).unwrap();
+        symlink(outside.join("linked.pdf"), dir.join("a/file-link.pdf")).unwrap();
+        symlink(dir.join("missing.pdf"), dir.join("a/broken.pdf")).unwrap();
+        // Back up to the base directory: a cycle
+        symlink(&dir, dir.join("a/loop")).unwrap();
+
+        let options = ScanOptions {
+            max_depth: None,
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            scan(&dir, &options),
+            ["a/file-link.pdf", "a/real.pdf", "linked/linked.pdf"]
+        );
+        let options = ScanOptions {
+            links: LinkPolicy::Skip,
+            ..options
+        };
+        assert_eq!(scan(&dir, &options), ["a/real.pdf"]);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_dir_all(&outside).unwrap();
+    }
+
+    #[test]
+    fn several_bases() {
+        let dir = fixtures::scratch_dir("scan-bases");
+        touch(&dir, &["x/a/one.pdf", "y/b/two.pdf"]);
+        let bases = [dir.join("x"), dir.join("y")];
+        let found = scan_directories(&bases, &ScanOptions::default()).unwrap();
+        assert_eq!(found, [dir.join("x/a/one.pdf"), dir.join("y/b/two.pdf")]);
+        assert_eq!(relative_path(&found[1], &bases), Path::new("y/b/two.pdf"));
+        assert_eq!(
+            relative_path(&found[1], &bases[1..]),
+            Path::new("b/two.pdf")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
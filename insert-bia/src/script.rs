@@ -0,0 +1,175 @@
+// A Rhai script deciding what files map to, for site-specific matching the
+// rules cannot express. The script defines
+//
+//     fn match_file(filename, folder, rows) { ... }
+//
+// where `rows` maps each Excel key to #{pages, position, cover, output_name,
+// excluded}. It returns () to leave the file to the usual matching, an Excel
+// key, a page number or array of pages, or a map with `key` or `page`/`pages`
+// and optionally `cover`, `position` and `output_name`.
+
+use crate::mapping::{output_file_name, MappingEntry};
+use crate::position::Position;
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::collections::HashMap;
+use std::path::Path;
+
+const MATCH_FN: &str = "match_file";
+
+pub struct MatchScript {
+    engine: Engine,
+    ast: AST,
+    // The Excel rows, built once for every call
+    rows: Map,
+}
+
+// What the script decided for a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptDecision {
+    // Use this Excel key's entry, with the overrides
+    Key(String, ScriptOverrides),
+    // Insert these pages, without looking at the Excel file
+    Pages(Vec<u32>, ScriptOverrides),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptOverrides {
+    // As written by the script; relative to the cover PDF's directory
+    pub cover: Option<String>,
+    pub position: Option<Position>,
+    pub output_name: Option<String>,
+}
+
+impl MatchScript {
+    pub fn load(
+        path: &Path,
+        mappings: &HashMap<String, MappingEntry>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let engine = Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if !ast.iter_functions().any(|f| f.name == MATCH_FN) {
+            return Err(format!("{} does not define fn {}", path.display(), MATCH_FN).into());
+        }
+
+        let rows = mappings
+            .iter()
+            .map(|(key, entry)| (key.into(), entry_to_dynamic(entry)))
+            .collect();
+        Ok(MatchScript { engine, ast, rows })
+    }
+
+    // The script's decision for the file `filename` in the folder `folder`
+    // (empty directly in a scanned directory), None to match it as usual
+    pub fn decide(
+        &self,
+        filename: &str,
+        folder: &str,
+    ) -> Result<Option<ScriptDecision>, Box<dyn std::error::Error>> {
+        let result: Dynamic = self
+            .engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                MATCH_FN,
+                (filename.to_string(), folder.to_string(), self.rows.clone()),
+            )
+            .map_err(|e| format!("{}: {}", filename, e))?;
+        parse_decision(result).map_err(|e| format!("{}: {}", filename, e).into())
+    }
+}
+
+fn entry_to_dynamic(entry: &MappingEntry) -> Dynamic {
+    let pages: Array = entry
+        .pages
+        .iter()
+        .map(|&page| Dynamic::from(page as i64))
+        .collect();
+    let mut row = Map::new();
+    row.insert("pages".into(), pages.into());
+    row.insert("position".into(), entry.position.to_string().into());
+    row.insert(
+        "cover".into(),
+        match &entry.cover {
+            Some(cover) => cover.display().to_string().into(),
+            None => Dynamic::UNIT,
+        },
+    );
+    row.insert(
+        "output_name".into(),
+        match &entry.output_name {
+            Some(name) => name.clone().into(),
+            None => Dynamic::UNIT,
+        },
+    );
+    row.insert("excluded".into(), entry.excluded.into());
+    row.into()
+}
+
+fn parse_decision(value: Dynamic) -> Result<Option<ScriptDecision>, String> {
+    if value.is_unit() {
+        return Ok(None);
+    }
+    if value.is_string() || value.is_int() || value.is_array() {
+        return parse_target(value, ScriptOverrides::default()).map(Some);
+    }
+
+    let Some(mut map) = value.try_cast::<Map>() else {
+        return Err(format!(
+            "{} returned neither (), a key, pages nor a map",
+            MATCH_FN
+        ));
+    };
+    let mut text = |field: &str| -> Result<Option<String>, String> {
+        match map.remove(field) {
+            None => Ok(None),
+            Some(value) if value.is_unit() => Ok(None),
+            Some(value) => value
+                .into_string()
+                .map(Some)
+                .map_err(|_| format!("'{}' is not a string", field)),
+        }
+    };
+    let overrides = ScriptOverrides {
+        cover: text("cover")?,
+        position: text("position")?.map(|p| p.parse()).transpose()?,
+        output_name: text("output_name")?
+            .map(|name| output_file_name(&name))
+            .transpose()?,
+    };
+    let target = match (map.remove("key"), map.remove("page"), map.remove("pages")) {
+        (Some(key), None, None) => key,
+        (None, Some(pages), None) | (None, None, Some(pages)) => pages,
+        _ => return Err("a map needs one of 'key', 'page' or 'pages'".to_string()),
+    };
+    parse_target(target, overrides).map(Some)
+}
+
+// A key, a page number or an array of them
+fn parse_target(value: Dynamic, overrides: ScriptOverrides) -> Result<ScriptDecision, String> {
+    if value.is_string() {
+        let key = value.into_string().unwrap_or_default();
+        return Ok(ScriptDecision::Key(key, overrides));
+    }
+
+    let values = if value.is_int() {
+        vec![value]
+    } else {
+        match value.try_cast::<Array>() {
+            Some(values) => values,
+            None => return Err("pages must be numbers".to_string()),
+        }
+    };
+    let pages = values
+        .into_iter()
+        .map(|page| match page.as_int() {
+            Ok(page) if page >= 1 && page <= u32::MAX as i64 => Ok(page as u32),
+            _ => Err(format!("invalid page {} (pages start at 1)", page)),
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+    if pages.is_empty() {
+        return Err("no pages".to_string());
+    }
+    Ok(ScriptDecision::Pages(pages, overrides))
+}
@@ -0,0 +1,61 @@
+// Digital signatures of the PDFs to process. A signature covers the bytes of
+// the file as signed, so any change to its pages leaves it invalid; signed
+// files are detected up front and, by default, left alone.
+
+use lopdf::{Dictionary, Document, Object};
+
+fn resolve<'a>(doc: &'a Document, object: &'a Object) -> Option<&'a Object> {
+    match object {
+        Object::Reference(id) => doc.get_object(*id).ok(),
+        object => Some(object),
+    }
+}
+
+// Whether `doc` carries a signature: a signature field with a value, or
+// certification permissions (/Perms) in its catalog
+pub(crate) fn is_signed(doc: &Document) -> bool {
+    let Ok(catalog) = doc.catalog() else {
+        return false;
+    };
+    if catalog.has(b"Perms") {
+        return true;
+    }
+    let fields = catalog
+        .get(b"AcroForm")
+        .ok()
+        .and_then(|form| resolve(doc, form)?.as_dict().ok())
+        .and_then(|form| resolve(doc, form.get(b"Fields").ok()?))
+        .and_then(|fields| fields.as_array().ok());
+
+    let mut pending: Vec<&Dictionary> = fields
+        .into_iter()
+        .flatten()
+        .filter_map(|field| resolve(doc, field)?.as_dict().ok())
+        .collect();
+    // Bounded, in case the fields refer back to themselves
+    let mut visited = 0;
+    while let Some(field) = pending.pop() {
+        visited += 1;
+        if visited > 10_000 {
+            break;
+        }
+        let is_signature = field
+            .get(b"FT")
+            .and_then(Object::as_name)
+            .is_ok_and(|kind| kind == b"Sig");
+        if is_signature && field.has(b"V") {
+            return true;
+        }
+        if let Some(kids) = field
+            .get(b"Kids")
+            .ok()
+            .and_then(|kids| resolve(doc, kids)?.as_array().ok())
+        {
+            pending.extend(
+                kids.iter()
+                    .filter_map(|kid| resolve(doc, kid)?.as_dict().ok()),
+            );
+        }
+    }
+    false
+}
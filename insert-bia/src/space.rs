@@ -0,0 +1,89 @@
+use crate::scan::base_dir_of;
+use crate::Job;
+use log::info;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Kept free on every volume so the run never fills it to the last byte
+const SAFETY_MARGIN: u64 = 64 * 1024 * 1024;
+
+// Refuse to start when the volumes written to are too full to finish the
+// job, instead of failing midway with IO errors from qpdf or the copy.
+// Sizes are estimates: each output is assumed to grow by the size of an
+// average bia.pdf page for every page inserted. A cover template's page is
+// made for each file and left out, being small next to the margin.
+pub fn check_disk_space(job: &Job) -> Result<(), Box<dyn std::error::Error>> {
+    let bia_size = match job.template {
+        Some(_) => 0,
+        None => fs::metadata(&job.config.bia_path)?.len(),
+    };
+    let page_size = bia_size / job.bia_page_count.max(1) as u64;
+
+    let size_of = |pdf_path: &PathBuf| fs::metadata(pdf_path).map(|m| m.len()).unwrap_or(0);
+    let growth_of = |pdf_path: &PathBuf| {
+        let pages = job
+            .lookup(pdf_path)
+            .map_or(0, |insertion| insertion.pages.len());
+        pages as u64 * page_size
+    };
+
+    // Temp outputs live next to their targets, one at a time, and every
+    // replaced file grows by the inserted pages
+    for base_dir in &job.config.dirs {
+        let files: Vec<&PathBuf> = job
+            .pdf_files
+            .iter()
+            .filter(|pdf_path| base_dir_of(pdf_path, &job.config.dirs) == Some(base_dir))
+            .collect();
+        let largest_output = files
+            .iter()
+            .map(|pdf_path| size_of(pdf_path) + growth_of(pdf_path))
+            .max()
+            .unwrap_or(0);
+        let total_growth: u64 = files.iter().map(|pdf_path| growth_of(pdf_path)).sum();
+        require_space(base_dir, largest_output + total_growth)?;
+    }
+
+    // Every original is copied when backing up
+    if let Some(backup_dir) = &job.config.backup_dir {
+        fs::create_dir_all(backup_dir)
+            .map_err(|e| format!("Failed to create backup directory: {}", e))?;
+        let total = job.pdf_files.iter().map(size_of).sum();
+        require_space(backup_dir, total)?;
+    }
+
+    Ok(())
+}
+
+fn require_space(path: &Path, required: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let available = fs4::available_space(path)
+        .map_err(|e| format!("Failed to check free space on {}: {}", path.display(), e))?;
+
+    info!(
+        "{}: {} needed, {} available",
+        path.display(),
+        format_size(required),
+        format_size(available)
+    );
+
+    if available < required.saturating_add(SAFETY_MARGIN) {
+        return Err(format!(
+            "Not enough disk space on {}: about {} needed, {} available",
+            path.display(),
+            format_size(required.saturating_add(SAFETY_MARGIN)),
+            format_size(available)
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    const MIB: u64 = 1024 * 1024;
+    if bytes >= 1024 * MIB {
+        format!("{:.1} GiB", bytes as f64 / (1024 * MIB) as f64)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / MIB as f64)
+    }
+}
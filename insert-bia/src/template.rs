@@ -0,0 +1,379 @@
+// Cover pages generated from a template filled in with the row of each file,
+// for personalized covers instead of the pages of bia.pdf. A template is a
+// text file, one instruction per line:
+//
+//     # comment
+//     size A4                      (or Letter, or a width and height in points)
+//     font Helvetica-Bold 24       (one of the standard PDF fonts, and a size)
+//     text 72 760 Customer: {Customer}
+//     line 72 740 523 740
+//
+// Positions are in points from the bottom left corner. `{Column}` is replaced
+// by the row's cell under that header (or column letter, e.g. `{C}`), and
+// `{filename}`, `{key}` and `{date}` (today, or the day of SOURCE_DATE_EPOCH
+// when set, for reproducible outputs) are always available. The
+// standard fonts only cover Latin-1, which has some Vietnamese letters but not
+// others, so all text is written without accents ("Nguyễn Thị Ánh" prints as
+// "Nguyen Thi Anh" rather than "Nguyen Thi Ánh").
+
+use crate::blank::{add_only_page, A4_MEDIA_BOX};
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Dictionary, Document, Object, StringFormat};
+use regex::Regex;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::LazyLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LETTER_MEDIA_BOX: [f32; 4] = [0.0, 0.0, 612.0, 792.0];
+
+const STANDARD_FONTS: [&str; 12] = [
+    "Helvetica",
+    "Helvetica-Bold",
+    "Helvetica-Oblique",
+    "Helvetica-BoldOblique",
+    "Times-Roman",
+    "Times-Bold",
+    "Times-Italic",
+    "Times-BoldItalic",
+    "Courier",
+    "Courier-Bold",
+    "Courier-Oblique",
+    "Courier-BoldOblique",
+];
+
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{([^{}]+)\}").expect("valid placeholder pattern"));
+
+#[derive(Debug, Clone)]
+enum Item {
+    // Font for the text that follows, as an index into `STANDARD_FONTS`
+    Font(usize, f32),
+    Text(f32, f32, String),
+    Line(f32, f32, f32, f32),
+}
+
+#[derive(Debug, Clone)]
+pub struct CoverTemplate {
+    media_box: [f32; 4],
+    items: Vec<Item>,
+}
+
+impl CoverTemplate {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        CoverTemplate::parse(&content).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    fn parse(content: &str) -> Result<Self, String> {
+        let mut template = CoverTemplate {
+            media_box: A4_MEDIA_BOX,
+            items: Vec::new(),
+        };
+
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let at_line = |e: String| format!("line {}: {}", index + 1, e);
+            let (instruction, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let mut args = rest.split_whitespace();
+            let number = |arg: Option<&str>, what: &str| -> Result<f32, String> {
+                let arg = arg.ok_or_else(|| at_line(format!("missing {}", what)))?;
+                arg.parse()
+                    .map_err(|_| at_line(format!("invalid {} '{}'", what, arg)))
+            };
+
+            match instruction.to_lowercase().as_str() {
+                "size" => {
+                    template.media_box = match rest.trim().to_lowercase().as_str() {
+                        "a4" => A4_MEDIA_BOX,
+                        "letter" => LETTER_MEDIA_BOX,
+                        _ => [
+                            0.0,
+                            0.0,
+                            number(args.next(), "width")?,
+                            number(args.next(), "height")?,
+                        ],
+                    }
+                }
+                "font" => {
+                    let name = args.next().ok_or_else(|| at_line("missing font".into()))?;
+                    let font = STANDARD_FONTS
+                        .iter()
+                        .position(|font| font.eq_ignore_ascii_case(name))
+                        .ok_or_else(|| {
+                            at_line(format!(
+                                "unknown font '{}' (use one of {})",
+                                name,
+                                STANDARD_FONTS.join(", ")
+                            ))
+                        })?;
+                    template
+                        .items
+                        .push(Item::Font(font, number(args.next(), "font size")?));
+                }
+                "text" => {
+                    let (x, y) = (number(args.next(), "x")?, number(args.next(), "y")?);
+                    // The text is the rest of the line, spaces included
+                    let text = rest
+                        .trim_start()
+                        .splitn(3, char::is_whitespace)
+                        .nth(2)
+                        .unwrap_or("")
+                        .to_string();
+                    template.items.push(Item::Text(x, y, text));
+                }
+                "line" => template.items.push(Item::Line(
+                    number(args.next(), "x1")?,
+                    number(args.next(), "y1")?,
+                    number(args.next(), "x2")?,
+                    number(args.next(), "y2")?,
+                )),
+                _ => return Err(at_line(format!("unknown instruction '{}'", instruction))),
+            }
+        }
+
+        Ok(template)
+    }
+
+    // Write the page for a file to `output`, with `fields` (compared
+    // case-insensitively) and `filename` and `key` filled in
+    pub fn render(
+        &self,
+        fields: &HashMap<String, String>,
+        filename: &str,
+        key: &str,
+        output: &Path,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut operations = Vec::new();
+        // Helvetica 12 until a font is given
+        let mut font = (0, 12.0);
+        let mut fonts_used = Vec::new();
+        for item in &self.items {
+            match item {
+                Item::Font(index, size) => font = (*index, *size),
+                Item::Text(x, y, text) => {
+                    let filled = fill_in(text, fields, filename, key)?;
+
+                    if !fonts_used.contains(&font.0) {
+                        fonts_used.push(font.0);
+                    }
+                    let font_name = Object::Name(format!("F{}", font.0).into_bytes());
+                    operations.extend([
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec![font_name, font.1.into()]),
+                        Operation::new("Td", vec![(*x).into(), (*y).into()]),
+                        Operation::new(
+                            "Tj",
+                            vec![Object::String(win_ansi(&filled), StringFormat::Literal)],
+                        ),
+                        Operation::new("ET", vec![]),
+                    ]);
+                }
+                Item::Line(x1, y1, x2, y2) => operations.extend([
+                    Operation::new("m", vec![(*x1).into(), (*y1).into()]),
+                    Operation::new("l", vec![(*x2).into(), (*y2).into()]),
+                    Operation::new("S", vec![]),
+                ]),
+            }
+        }
+        let contents = Content { operations }.encode()?;
+
+        let mut doc = Document::with_version("1.5");
+        let mut font_resources = Dictionary::new();
+        for index in fonts_used {
+            let font_id = doc.add_object(dictionary! {
+                "Type" => "Font",
+                "Subtype" => "Type1",
+                "BaseFont" => STANDARD_FONTS[index],
+                "Encoding" => "WinAnsiEncoding",
+            });
+            font_resources.set(format!("F{}", index), font_id);
+        }
+        let resources = dictionary! { "Font" => font_resources };
+        add_only_page(&mut doc, self.media_box, contents, resources);
+        doc.save(output)?;
+        Ok(())
+    }
+}
+
+// Whether `text` names any of the row's cells, which are then needed
+pub fn uses_fields(text: &str) -> bool {
+    PLACEHOLDER.captures_iter(text).any(|placeholder| {
+        !matches!(
+            placeholder[1].trim().to_lowercase().as_str(),
+            "filename" | "key" | "date"
+        )
+    })
+}
+
+// The time given in seconds by SOURCE_DATE_EPOCH, else the current time
+fn now() -> SystemTime {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|seconds| seconds.trim().parse().ok())
+        .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+        .unwrap_or_else(SystemTime::now)
+}
+
+// `text` with each `{name}` replaced by the cell of `fields` under that name
+// (compared case-insensitively), or by the file's name, its key or today's
+// date
+pub fn fill_in(
+    text: &str,
+    fields: &HashMap<String, String>,
+    filename: &str,
+    key: &str,
+) -> Result<String, String> {
+    let field = |name: &str| -> Result<String, String> {
+        let wanted = name.trim().to_lowercase();
+        match wanted.as_str() {
+            "filename" => return Ok(filename.to_string()),
+            "key" => return Ok(key.to_string()),
+            "date" => {
+                let now = humantime::format_rfc3339(now()).to_string();
+                return Ok(now[..10].to_string());
+            }
+            _ => {}
+        }
+        fields
+            .iter()
+            .find(|(column, _)| column.trim().to_lowercase() == wanted)
+            .map(|(_, value)| value.clone())
+            .ok_or_else(|| format!("the row has no column '{}' for {{{}}}", name, name))
+    };
+
+    let mut filled = String::new();
+    let mut last = 0;
+    for placeholder in PLACEHOLDER.captures_iter(text) {
+        let whole = placeholder.get(0).expect("group 0 is the match");
+        filled.push_str(&text[last..whole.start()]);
+        filled.push_str(&field(&placeholder[1])?);
+        last = whole.end();
+    }
+    filled.push_str(&text[last..]);
+    Ok(filled)
+}
+
+// `text` in the WinAnsi encoding of the standard fonts, transliterated to
+// ASCII as a whole so that no letter keeps an accent while the next loses its
+// own
+pub(crate) fn win_ansi(text: &str) -> Vec<u8> {
+    deunicode::deunicode_with_tofu(text, "?").into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn parses_instructions() {
+        let template = CoverTemplate::parse(
+            "# a comment\n\
+             size Letter\n\
+             \n\
+             FONT times-bold 24\n\
+             text 72 760 Customer:  {Customer}\n\
+             line 72 740 523.5 740\n",
+        )
+        .unwrap();
+        assert_eq!(template.media_box, LETTER_MEDIA_BOX);
+        assert!(matches!(template.items[0], Item::Font(5, size) if size == 24.0));
+        assert!(matches!(
+            &template.items[1],
+            Item::Text(x, y, text) if (*x, *y) == (72.0, 760.0) && text == "Customer:  {Customer}"
+        ));
+        assert!(matches!(template.items[2], Item::Line(_, _, x2, _) if x2 == 523.5));
+
+        let template = CoverTemplate::parse("size 300 400").unwrap();
+        assert_eq!(template.media_box, [0.0, 0.0, 300.0, 400.0]);
+        assert_eq!(CoverTemplate::parse("").unwrap().media_box, A4_MEDIA_BOX);
+    }
+
+    #[test]
+    fn reports_the_bad_line() {
+        let error = |content: &str| CoverTemplate::parse(content).unwrap_err();
+        assert_eq!(error("# ok\ntext 72"), "line 2: missing y");
+        assert_eq!(error("line 1 2 x 4"), "line 1: invalid x2 'x'");
+        assert_eq!(error("size huge"), "line 1: invalid width 'huge'");
+        assert!(error("font Arial 12").starts_with("line 1: unknown font 'Arial' (use one of"));
+        assert_eq!(
+            error("circle 1 2 3"),
+            "line 1: unknown instruction 'circle'"
+        );
+    }
+
+    #[test]
+    fn fills_in_placeholders() {
+        let fields = HashMap::from([
+            ("Customer ".to_string(), "Nguyễn Thị Ánh".to_string()),
+            ("C".to_string(), "42".to_string()),
+        ]);
+        assert_eq!(
+            fill_in(
+                "{customer} - {C} ({filename}, {KEY})",
+                &fields,
+                "a.pdf",
+                "HD001"
+            )
+            .unwrap(),
+            "Nguyễn Thị Ánh - 42 (a.pdf, HD001)"
+        );
+        assert_eq!(
+            fill_in("{date}", &fields, "a.pdf", "HD001").unwrap().len(),
+            10
+        );
+        assert_eq!(
+            fill_in("{Phone}", &fields, "a.pdf", "HD001").unwrap_err(),
+            "the row has no column 'Phone' for {Phone}"
+        );
+        assert_eq!(
+            fill_in("no fields", &fields, "a.pdf", "HD001").unwrap(),
+            "no fields"
+        );
+
+        assert!(uses_fields("{filename} {Customer}"));
+        assert!(!uses_fields("{filename} {key} { Date }"));
+    }
+
+    #[test]
+    fn text_loses_every_accent() {
+        assert_eq!(win_ansi("Nguyễn Thị Ánh"), b"Nguyen Thi Anh");
+        assert_eq!(win_ansi("Đặng Văn Hùng"), b"Dang Van Hung");
+        assert_eq!(win_ansi("Café (1/2)"), b"Cafe (1/2)");
+    }
+
+    #[test]
+    fn renders_a_page() {
+        let dir = fixtures::scratch_dir("template-render");
+        let output = dir.join("cover.pdf");
+        let template =
+            CoverTemplate::parse("font Courier 10\ntext 10 20 {key}: {Customer}").unwrap();
+        let fields = HashMap::from([("Customer".to_string(), "Lê Văn Tám".to_string())]);
+        template.render(&fields, "a.pdf", "HD001", &output).unwrap();
+
+        let doc = Document::load(&output).unwrap();
+        let page_id = doc.get_pages()[&1];
+        let content = Content::decode(&doc.get_page_content(page_id)).unwrap();
+        let text = content
+            .operations
+            .iter()
+            .find(|op| op.operator == "Tj")
+            .unwrap();
+        assert_eq!(text.operands[0].as_str().unwrap(), b"HD001: Le Van Tam");
+        let fonts = doc.get_page_fonts(page_id).unwrap();
+        assert_eq!(
+            fonts[b"F8".as_slice()]
+                .get(b"BaseFont")
+                .unwrap()
+                .as_name()
+                .unwrap(),
+            b"Courier"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,86 @@
+// Text stamped diagonally across every page of a processed file, e.g. "BẢN
+// SAO" or the document's ID, filled in like a cover template from the file's
+// row ("{DocID} {date}"). Like the cover templates it uses a standard font, so
+// the text is written without accents.
+
+use crate::blank::{add_only_page, first_page_media_box, A4_MEDIA_BOX};
+use crate::template::win_ansi;
+use lopdf::content::{Content, Operation};
+use lopdf::{dictionary, Document, Object, StringFormat};
+use std::path::Path;
+
+const FONT: &str = "Helvetica-Bold";
+// Average width of a Helvetica-Bold character, in units of the font size
+const CHAR_WIDTH: f32 = 0.6;
+const MAX_FONT_SIZE: f32 = 96.0;
+// Gray, and see-through so the page stays readable
+const GRAY: f32 = 0.5;
+const OPACITY: f32 = 0.3;
+
+// Write a one-page PDF the size of `target`'s first page (A4 when it cannot be
+// read) with `text` across its diagonal to `output`, for qpdf to overlay onto
+// the pages
+pub fn write_watermark_page(
+    text: &str,
+    target: &Path,
+    output: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let media_box = first_page_media_box(target).unwrap_or(A4_MEDIA_BOX);
+    let [left, bottom, right, top] = media_box;
+    let (width, height) = (right - left, top - bottom);
+    let diagonal = width.hypot(height);
+    let angle = height.atan2(width);
+
+    // Sized to fill most of the diagonal
+    let chars = text.chars().count().max(1) as f32;
+    let size = (diagonal * 0.7 / (chars * CHAR_WIDTH)).min(MAX_FONT_SIZE);
+    let text_width = chars * CHAR_WIDTH * size;
+
+    let (sin, cos) = angle.sin_cos();
+    let operations = vec![
+        Operation::new("q", vec![]),
+        Operation::new("gs", vec![Object::Name(b"GS0".to_vec())]),
+        Operation::new("g", vec![GRAY.into()]),
+        Operation::new("BT", vec![]),
+        Operation::new("Tf", vec![Object::Name(b"F0".to_vec()), size.into()]),
+        // Rotated about the centre of the page, the text centred on it
+        Operation::new(
+            "Tm",
+            vec![
+                cos.into(),
+                sin.into(),
+                (-sin).into(),
+                cos.into(),
+                (left + width / 2.0).into(),
+                (bottom + height / 2.0).into(),
+            ],
+        ),
+        Operation::new("Td", vec![(-text_width / 2.0).into(), (-size / 3.0).into()]),
+        Operation::new(
+            "Tj",
+            vec![Object::String(win_ansi(text), StringFormat::Literal)],
+        ),
+        Operation::new("ET", vec![]),
+        Operation::new("Q", vec![]),
+    ];
+    let contents = Content { operations }.encode()?;
+
+    let mut doc = Document::with_version("1.5");
+    let font_id = doc.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => FONT,
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let state_id = doc.add_object(dictionary! {
+        "Type" => "ExtGState",
+        "ca" => OPACITY,
+    });
+    let resources = dictionary! {
+        "Font" => dictionary! { "F0" => font_id },
+        "ExtGState" => dictionary! { "GS0" => state_id },
+    };
+    add_only_page(&mut doc, media_box, contents, resources);
+    doc.save(output)?;
+    Ok(())
+}